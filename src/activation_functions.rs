@@ -1,5 +1,95 @@
-pub fn sigmoid(x: f64) -> f64 {
-    1.0 / (1.0 + (-1.0 * x).exp())
+use crate::config::SigmoidMode;
+use crate::float::Float;
+
+/// Dot products outside `[-MAX_EXP, MAX_EXP]` are clipped by the table.
+pub const MAX_EXP: f64 = 6.0;
+pub const EXP_TABLE_SIZE: usize = 1000;
+
+pub fn sigmoid<T: Float>(x: T) -> T {
+    T::one() / (T::one() + (-x).exp())
+}
+
+/// `ln(sigmoid(x))` without overflowing to `-inf` for large `|x|`.
+pub fn log_sigmoid<T: Float>(x: T) -> T {
+    if x >= T::zero() {
+        -(-x).exp().ln_1p()
+    } else {
+        x - x.exp().ln_1p()
+    }
+}
+
+/// Sigmoid used in the training steps, either exact or a word2vec style
+/// `EXP_TABLE` lookup.
+#[derive(Debug, Clone)]
+pub struct Sigmoid<T: Float> {
+    table: Option<Vec<T>>,
+    log_table: Option<Vec<T>>,
+}
+
+impl<T: Float> Sigmoid<T> {
+    pub fn new(mode: SigmoidMode) -> Sigmoid<T> {
+        let table_of = |f: fn(f64) -> f64| -> Vec<T> {
+            (0..EXP_TABLE_SIZE)
+                .map(|i| {
+                    let x = (i as f64 / EXP_TABLE_SIZE as f64 * 2.0 - 1.0) * MAX_EXP;
+                    T::cast(f(x))
+                })
+                .collect()
+        };
+        match mode {
+            SigmoidMode::Exact => Sigmoid {
+                table: None,
+                log_table: None,
+            },
+            SigmoidMode::Table => Sigmoid {
+                table: Some(table_of(sigmoid)),
+                log_table: Some(table_of(log_sigmoid)),
+            },
+        }
+    }
+
+    pub fn mode(&self) -> SigmoidMode {
+        match self.table {
+            Some(_) => SigmoidMode::Table,
+            None => SigmoidMode::Exact,
+        }
+    }
+
+    fn table_index(x: f64) -> Option<usize> {
+        if x <= -MAX_EXP || x >= MAX_EXP {
+            return None;
+        }
+        Some(((x + MAX_EXP) * (EXP_TABLE_SIZE as f64 / MAX_EXP / 2.0)) as usize)
+    }
+
+    /// The sigmoid of `x`, or `None` when the table is used and `x` is
+    /// outside the clipped range, in which case the gradient is negligible
+    /// and the update should be skipped.
+    pub fn clipped(&self, x: T) -> Option<T> {
+        match &self.table {
+            None => Some(sigmoid(x)),
+            Some(table) => Sigmoid::<T>::table_index(x.as_f64()).map(|i| table[i]),
+        }
+    }
+
+    /// The sigmoid of `x`, clamped to 0 or 1 when the table is used and
+    /// `x` is outside the clipped range.
+    pub fn clamped(&self, x: T) -> T {
+        self.clipped(x)
+            .unwrap_or(if x > T::zero() { T::one() } else { T::zero() })
+    }
+
+    /// `ln(sigmoid(x))` for the loss, looked up in the table within the
+    /// clipped range and computed with `log_sigmoid` outside of it.
+    pub fn log(&self, x: T) -> T {
+        match &self.log_table {
+            Some(table) => match Sigmoid::<T>::table_index(x.as_f64()) {
+                Some(i) => table[i],
+                None => log_sigmoid(x),
+            },
+            None => log_sigmoid(x),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -10,5 +100,33 @@ mod sigmoid_test {
     fn test_sigmoid() {
         let x = 0.0;
         assert_eq!(sigmoid(x), 0.5);
+        assert_eq!(sigmoid(0.0f32), 0.5f32);
+    }
+
+    #[test]
+    fn test_log_sigmoid() {
+        assert!((log_sigmoid(0.0) - 0.5f64.ln()).abs() < 1e-12);
+        assert!((log_sigmoid(2.0) - sigmoid(2.0f64).ln()).abs() < 1e-12);
+        assert!((log_sigmoid(-2.0) - sigmoid(-2.0f64).ln()).abs() < 1e-12);
+        assert_eq!(log_sigmoid(-1000.0), -1000.0);
+        assert!(log_sigmoid(-1000.0f32).is_finite());
+        assert_eq!(log_sigmoid(1000.0), 0.0);
+    }
+
+    #[test]
+    fn test_sigmoid_table() {
+        let table: Sigmoid<f64> = Sigmoid::new(SigmoidMode::Table);
+        let exact: Sigmoid<f64> = Sigmoid::new(SigmoidMode::Exact);
+        for i in -59..60 {
+            let x = i as f64 / 10.0;
+            assert!((table.clipped(x).unwrap() - exact.clipped(x).unwrap()).abs() < 0.01);
+        }
+        for i in -100..100 {
+            let x = i as f64 / 10.0;
+            assert!((table.log(x) - exact.log(x)).abs() < 0.02);
+        }
+        assert_eq!(table.clipped(6.0), None);
+        assert_eq!(table.clipped(-7.5), None);
+        assert!(exact.clipped(7.5).is_some());
     }
 }
@@ -0,0 +1,88 @@
+use rand::Rng;
+
+/// Walker's alias method for O(1) sampling from a discrete distribution.
+#[derive(Debug)]
+pub struct AliasTable {
+    prob: Vec<f64>,
+    alias: Vec<usize>,
+}
+
+impl AliasTable {
+    /// Builds the table from non negative, not necessarily normalized weights.
+    pub fn new(weights: &[f64]) -> AliasTable {
+        let n = weights.len();
+        let total: f64 = weights.iter().sum();
+        let mut prob = vec![0.0; n];
+        let mut alias = vec![0; n];
+        if n == 0 || total <= 0.0 {
+            return AliasTable {
+                prob: vec![1.0; n],
+                alias: (0..n).collect(),
+            };
+        }
+
+        let mut scaled: Vec<f64> = weights.iter().map(|w| w * n as f64 / total).collect();
+        let mut small = Vec::with_capacity(n);
+        let mut large = Vec::with_capacity(n);
+        for (i, p) in scaled.iter().enumerate() {
+            if *p < 1.0 {
+                small.push(i);
+            } else {
+                large.push(i);
+            }
+        }
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            prob[s] = scaled[s];
+            alias[s] = l;
+            scaled[l] -= 1.0 - scaled[s];
+            if scaled[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // Whatever is left is 1 up to rounding.
+        for i in small.into_iter().chain(large) {
+            prob[i] = 1.0;
+            alias[i] = i;
+        }
+        AliasTable { prob, alias }
+    }
+
+    pub fn len(&self) -> usize {
+        self.prob.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prob.is_empty()
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        let i = rng.gen_range(0, self.prob.len());
+        if rng.gen::<f64>() < self.prob[i] {
+            i
+        } else {
+            self.alias[i]
+        }
+    }
+}
+
+#[cfg(test)]
+mod alias_tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_alias_table() {
+        let table = AliasTable::new(&[1.0, 0.0, 3.0, 4.0]);
+        let mut counts = [0; 4];
+        let mut rng = thread_rng();
+        for _ in 0..80000 {
+            counts[table.sample(&mut rng)] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!((counts[0] as f64 / 80000.0 - 0.125).abs() < 0.01);
+        assert!((counts[2] as f64 / 80000.0 - 0.375).abs() < 0.01);
+        assert!((counts[3] as f64 / 80000.0 - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,172 @@
+//! Node attributes, labels keyed by node id, e.g. the known communities of
+//! a graph.
+//!
+//! An attribute file has one `node_id value ...` line per node, split like
+//! the edge list by `delimiter` and skipping blank and comment lines.  If
+//! the first field of the first line is not a node id, that line is a
+//! header naming the attributes, otherwise they are named by their zero
+//! based column, `1`, `2`, ...  Empty fields are missing values.
+
+use crate::error::DeepwalkError;
+use crate::graph::NodeID;
+use crate::input;
+use crate::loader::EdgeListFormat;
+use log::info;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NodeAttributes {
+    names: Vec<String>,
+    values: HashMap<NodeID, Vec<String>>,
+}
+
+impl NodeAttributes {
+    /// Reads the attribute file `filename`, which may be compressed or `-`
+    /// for stdin.  Every line must have a value for every attribute, and a
+    /// node may only appear once.
+    pub fn load(filename: &str, format: &EdgeListFormat) -> Result<NodeAttributes, DeepwalkError> {
+        NodeAttributes::read(input::open(filename)?, format)
+    }
+
+    /// Reads an attribute file from `input`, see `load`.
+    pub fn read<R: BufRead>(
+        input: R,
+        format: &EdgeListFormat,
+    ) -> Result<NodeAttributes, DeepwalkError> {
+        let mut attributes = NodeAttributes::default();
+        let mut first = true;
+        for (i, line) in input.lines().enumerate() {
+            let line = line?;
+            if format.is_skipped(&line) {
+                continue;
+            }
+            let fields = format.fields(&line);
+            let parse_error = |message: String| DeepwalkError::Parse {
+                line: i + 1,
+                message,
+            };
+            let node = fields[0].parse::<NodeID>();
+            if first {
+                first = false;
+                if node.is_err() {
+                    attributes.names = fields[1..].iter().map(|f| f.to_string()).collect();
+                    continue;
+                }
+                attributes.names = (1..fields.len()).map(|c| c.to_string()).collect();
+            }
+            let node =
+                node.map_err(|_| parse_error(format!("{:?} is not a valid node id", fields[0])))?;
+            if fields.len() != attributes.names.len() + 1 {
+                return Err(parse_error(format!(
+                    "expected a node id and {} attributes, found {} fields",
+                    attributes.names.len(),
+                    fields.len()
+                )));
+            }
+            let values = fields[1..].iter().map(|f| f.to_string()).collect();
+            if attributes.values.insert(node, values).is_some() {
+                return Err(parse_error(format!("node {} appears twice", node)));
+            }
+        }
+        info!(
+            "Read {} attributes of {} nodes",
+            attributes.names.len(),
+            attributes.values.len()
+        );
+        Ok(attributes)
+    }
+
+    /// The attribute names, in file order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The position of the attribute `name`.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    /// The number of nodes with attributes.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Every attribute value of `node`, `None` if the node has none.
+    pub fn get_all(&self, node: NodeID) -> Option<&[String]> {
+        self.values.get(&node).map(Vec::as_slice)
+    }
+
+    /// The value of attribute `column` of `node`, `None` if the node has
+    /// no attributes or the value is missing.
+    pub fn get(&self, node: NodeID, column: usize) -> Option<&str> {
+        self.values
+            .get(&node)
+            .and_then(|values| values.get(column))
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod attributes_tests {
+    use super::*;
+
+    fn read_str(contents: &str, format: &EdgeListFormat) -> Result<NodeAttributes, DeepwalkError> {
+        NodeAttributes::read(contents.as_bytes(), format)
+    }
+
+    #[test]
+    fn test_load() {
+        let csv = EdgeListFormat {
+            delimiter: Some(','),
+            ..EdgeListFormat::default()
+        };
+        let contents = "id,club,age\n# a comment\n1,hi,31\n\n2,officer,\n3,hi,27.5\n";
+        let attributes = read_str(contents, &csv).unwrap();
+        assert_eq!(attributes.names(), &["club", "age"]);
+        assert_eq!(attributes.len(), 3);
+        let club = attributes.column("club").unwrap();
+        assert_eq!(attributes.get(2, club), Some("officer"));
+        assert_eq!(attributes.get(4, club), None);
+        let age = attributes.column("age").unwrap();
+        assert_eq!(attributes.get(3, age), Some("27.5"));
+        assert_eq!(attributes.get(2, age), None);
+
+        let contents = "1 0 0.5\n2 1 1.5\n";
+        let attributes = read_str(contents, &EdgeListFormat::default()).unwrap();
+        assert_eq!(attributes.names(), &["1", "2"]);
+        assert_eq!(attributes.get(2, 1), Some("1.5"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let format = EdgeListFormat::default();
+        let cases = [
+            ("id label\n1 a\n2\n", 3),
+            ("1 a\n2 b c\n", 2),
+            ("1 a\nx b\n", 2),
+            ("id label\n1 a\n1 b\n", 3),
+        ];
+        for (contents, line) in cases.iter() {
+            match read_str(contents, &format) {
+                Err(DeepwalkError::Parse { line: l, .. }) => assert_eq!(l, *line, "{}", contents),
+                other => panic!("expected a parse error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_karate_communities() {
+        let attributes =
+            NodeAttributes::load("karate_communities.txt", &EdgeListFormat::default()).unwrap();
+        assert_eq!(attributes.len(), 34);
+        let community = attributes.column("community").unwrap();
+        assert_eq!(attributes.get(1, community), Some("0"));
+        assert_eq!(attributes.get(34, community), Some("1"));
+    }
+}
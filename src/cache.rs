@@ -0,0 +1,516 @@
+//! A binary cache of a built graph, so repeated runs on the same input skip
+//! parsing.
+//!
+//! All integers are little endian and every section starts 8 byte aligned:
+//!
+//! ```text
+//! magic        8 bytes "DWGRAPH\0"
+//! version      u32
+//! flags        u32, 1 if the graph is weighted
+//! source_crc   u32, CRC32 of the input file
+//! options_crc  u32, CRC32 of the loader options
+//! source_len   u64, length of the input file
+//! source_mtime u64, modification time of the input file in nanoseconds
+//!              since the epoch, 0 if unknown
+//! body_crc     u32, CRC32 of everything after the header
+//! padding      u32
+//! num_nodes    u64
+//! num_entries  u64, the sum of all degrees
+//! node_ids     i32 x num_nodes, in index order, padded to 8 bytes
+//! offsets      u64 x (num_nodes + 1), into neighbors
+//! neighbors    i32 x num_entries, neighbor node ids, padded to 8 bytes
+//! weights      f32 x num_entries, only if weighted
+//! ```
+//!
+//! A cache whose input length or loader options differ from the current run
+//! is stale and rebuilt, as is a corrupt one.  If only the modification time
+//! of the input differs, its checksum decides, so an unchanged input is only
+//! read in full when it was touched.
+
+use crate::error::DeepwalkError;
+use crate::graph::{Graph, NodeID};
+use log::{info, warn};
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"DWGRAPH\0";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 48;
+const BODY_CRC_AT: u64 = 40;
+const WEIGHTED: u32 = 1;
+
+/// Identifies the input a cache was built from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fingerprint {
+    pub source_len: u64,
+    /// Nanoseconds since the epoch, 0 if the file system doesn't say.
+    pub source_mtime: u64,
+    pub source_crc: u32,
+    /// Loader options that change the graph built from the same file.
+    pub options_crc: u32,
+}
+
+impl Fingerprint {
+    /// The length and modification time of the file `path` and the
+    /// checksum of the loader `options`, without reading the file.
+    /// `source_crc` is 0 until `checksum` is called.
+    pub fn stat(path: &str, options: &str) -> io::Result<Fingerprint> {
+        let metadata = fs::metadata(path)?;
+        let source_mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as u64);
+        Ok(Fingerprint {
+            source_len: metadata.len(),
+            source_mtime,
+            source_crc: 0,
+            options_crc: crc32fast::hash(options.as_bytes()),
+        })
+    }
+
+    /// Stats and checksums the file `path` and the description of the
+    /// loader `options`.
+    pub fn of_file(path: &str, options: &str) -> io::Result<Fingerprint> {
+        let mut fingerprint = Fingerprint::stat(path, options)?;
+        fingerprint.source_crc = checksum(path)?;
+        Ok(fingerprint)
+    }
+}
+
+fn checksum(path: &str) -> io::Result<u32> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Reads the graph from the cache at `path` if it was built from the file
+/// `source` with the loader `options`, otherwise calls `build` and writes
+/// its graph to `path`.
+pub fn load_or_build<F>(
+    path: &str,
+    source: &str,
+    options: &str,
+    build: F,
+) -> Result<Graph, DeepwalkError>
+where
+    F: FnOnce() -> Result<Graph, DeepwalkError>,
+{
+    let mut fingerprint = Fingerprint::stat(source, options)?;
+    let mut source_crc = None;
+    match File::open(path)
+        .map_err(DeepwalkError::from)
+        .and_then(|file| read_header(path, BufReader::new(file)))
+    {
+        Ok((header, mut reader)) => {
+            let cached = header.fingerprint;
+            let current = if fingerprint.source_len != cached.source_len
+                || fingerprint.options_crc != cached.options_crc
+            {
+                false
+            } else if fingerprint.source_mtime != 0
+                && fingerprint.source_mtime == cached.source_mtime
+            {
+                true
+            } else {
+                // Touched, but maybe not changed.
+                let crc = checksum(source)?;
+                source_crc = Some(crc);
+                crc == cached.source_crc
+            };
+            if !current {
+                info!("The graph cache {} is stale, rebuilding it", path);
+            } else {
+                match read_body(path, &mut reader, &header) {
+                    Ok(graph) => {
+                        info!("Read the graph from the cache {}", path);
+                        return Ok(graph);
+                    }
+                    Err(e) => warn!("Rebuilding the graph cache {}: {}", path, e),
+                }
+            }
+        }
+        Err(DeepwalkError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Rebuilding the graph cache {}: {}", path, e),
+    }
+    fingerprint.source_crc = match source_crc {
+        Some(crc) => crc,
+        None => checksum(source)?,
+    };
+    let graph = build()?;
+    if let Err(e) = write(&graph, path, fingerprint) {
+        warn!("Could not write the graph cache {}: {}", path, e);
+    }
+    Ok(graph)
+}
+
+/// Writes `graph` to `path` through a temporary file, so an interrupted
+/// write never leaves a truncated cache.  The temporary file is removed if
+/// the write fails.
+pub fn write(graph: &Graph, path: &str, fingerprint: Fingerprint) -> io::Result<()> {
+    let tmp = format!("{}.tmp", path);
+    let result = write_file(graph, &tmp, fingerprint).and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn write_file(graph: &Graph, path: &str, fingerprint: Fingerprint) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    let weighted = graph.is_weighted();
+    let flags = if weighted { WEIGHTED } else { 0 };
+    writer.write_all(&flags.to_le_bytes())?;
+    writer.write_all(&fingerprint.source_crc.to_le_bytes())?;
+    writer.write_all(&fingerprint.options_crc.to_le_bytes())?;
+    writer.write_all(&fingerprint.source_len.to_le_bytes())?;
+    writer.write_all(&fingerprint.source_mtime.to_le_bytes())?;
+    // The body checksum is patched in once the body is written.
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+
+    let mut body = BodyWriter {
+        inner: writer,
+        hasher: crc32fast::Hasher::new(),
+        len: 0,
+    };
+    let node_ids = graph.node_ids_by_idx();
+    body.write_all(&(node_ids.len() as u64).to_le_bytes())?;
+    let num_entries: usize = graph.get_node_iter().map(|n| n.get_neighbors().len()).sum();
+    body.write_all(&(num_entries as u64).to_le_bytes())?;
+    for id in &node_ids {
+        body.write_all(&id.to_le_bytes())?;
+    }
+    body.pad()?;
+    let mut offset = 0u64;
+    body.write_all(&offset.to_le_bytes())?;
+    for id in &node_ids {
+        offset += graph.get_node(id).unwrap().get_neighbors().len() as u64;
+        body.write_all(&offset.to_le_bytes())?;
+    }
+    for id in &node_ids {
+        for neighbor in graph.get_node(id).unwrap().get_neighbors() {
+            body.write_all(&neighbor.to_le_bytes())?;
+        }
+    }
+    if weighted {
+        body.pad()?;
+        for id in &node_ids {
+            let node = graph.get_node(id).unwrap();
+            match node.get_edge_weights() {
+                Some(weights) => {
+                    for w in weights {
+                        body.write_all(&w.to_le_bytes())?;
+                    }
+                }
+                None => {
+                    for _ in node.get_neighbors() {
+                        body.write_all(&1f32.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+    }
+
+    let body_crc = body.hasher.finalize();
+    let mut file = body.inner.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(BODY_CRC_AT))?;
+    file.write_all(&body_crc.to_le_bytes())?;
+    file.sync_all()
+}
+
+/// Checksums and counts the bytes of the body on their way to the file.
+struct BodyWriter<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+    len: usize,
+}
+
+impl<W: Write> BodyWriter<W> {
+    /// Pads the body with zeros to a multiple of 8 bytes.
+    fn pad(&mut self) -> io::Result<()> {
+        let zeros = [0; 8];
+        self.write_all(&zeros[..padded(self.len) - self.len])
+    }
+}
+
+impl<W: Write> Write for BodyWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the graph and the fingerprint of its input from the cache `path`.
+pub fn read(path: &str) -> Result<(Graph, Fingerprint), DeepwalkError> {
+    let (header, mut reader) = read_header(path, BufReader::new(File::open(path)?))?;
+    let graph = read_body(path, &mut reader, &header)?;
+    Ok((graph, header.fingerprint))
+}
+
+struct Header {
+    fingerprint: Fingerprint,
+    weighted: bool,
+    body_crc: u32,
+}
+
+fn corrupt(path: &str, what: &str) -> DeepwalkError {
+    DeepwalkError::Format(format!("graph cache {}: {}", path, what))
+}
+
+/// Reads the header of the cache `path` from `reader`, leaving it at the
+/// start of the body.
+fn read_header<R: Read>(path: &str, mut reader: R) -> Result<(Header, R), DeepwalkError> {
+    let mut bytes = [0; HEADER_LEN];
+    if let Err(e) = reader.read_exact(&mut bytes) {
+        return Err(match e.kind() {
+            io::ErrorKind::UnexpectedEof => corrupt(path, "not a graph cache"),
+            _ => e.into(),
+        });
+    }
+    if &bytes[..8] != MAGIC {
+        return Err(corrupt(path, "not a graph cache"));
+    }
+    if u32_at(&bytes, 8) != VERSION {
+        return Err(corrupt(path, "written by another version"));
+    }
+    let header = Header {
+        fingerprint: Fingerprint {
+            source_crc: u32_at(&bytes, 16),
+            options_crc: u32_at(&bytes, 20),
+            source_len: u64_at(&bytes, 24),
+            source_mtime: u64_at(&bytes, 32),
+        },
+        weighted: u32_at(&bytes, 12) & WEIGHTED != 0,
+        body_crc: u32_at(&bytes, BODY_CRC_AT as usize),
+    };
+    Ok((header, reader))
+}
+
+/// Reads the rest of the cache `path` from `reader` and builds its graph.
+fn read_body<R: Read>(path: &str, reader: &mut R, header: &Header) -> Result<Graph, DeepwalkError> {
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body)?;
+    if body.len() < 16 {
+        return Err(corrupt(path, "not a graph cache"));
+    }
+    if crc32fast::hash(&body) != header.body_crc {
+        return Err(corrupt(path, "checksum mismatch"));
+    }
+
+    let num_nodes = u64_at(&body, 0) as usize;
+    let num_entries = u64_at(&body, 8) as usize;
+    if num_nodes > body.len() || num_entries > body.len() {
+        return Err(corrupt(path, "section lengths don't match the file"));
+    }
+    let ids_start = 16;
+    let offsets_start = ids_start + padded(4 * num_nodes);
+    let neighbors_start = offsets_start + 8 * (num_nodes + 1);
+    let weights_start = neighbors_start + padded(4 * num_entries);
+    let expected_len = if header.weighted {
+        weights_start + 4 * num_entries
+    } else {
+        neighbors_start + 4 * num_entries
+    };
+    if body.len() != expected_len {
+        return Err(corrupt(path, "section lengths don't match the file"));
+    }
+    let offset = |i| u64_at(&body, offsets_start + 8 * i) as usize;
+    let mut nodes = Vec::with_capacity(num_nodes);
+    for i in 0..num_nodes {
+        let (start, end) = (offset(i), offset(i + 1));
+        if start > end || end > num_entries {
+            return Err(corrupt(path, "invalid offsets"));
+        }
+        let neighbors = (start..end)
+            .map(|e| i32_at(&body, neighbors_start + 4 * e))
+            .collect();
+        let weights = if header.weighted {
+            (start..end)
+                .map(|e| f32::from_bits(u32_at(&body, weights_start + 4 * e)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        nodes.push((i32_at(&body, ids_start + 4 * i), neighbors, weights));
+    }
+    let graph = Graph::from_adjacency(nodes);
+    if graph.num_nodes() != num_nodes {
+        return Err(corrupt(path, "duplicate node ids"));
+    }
+    Ok(graph)
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(8) * 8
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn i32_at(bytes: &[u8], at: usize) -> NodeID {
+    NodeID::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fingerprint() -> Fingerprint {
+        Fingerprint {
+            source_len: 3,
+            source_mtime: 5,
+            source_crc: 7,
+            options_crc: 11,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut g = Graph::new();
+        g.build_graph_from_file("karate_network.txt").unwrap();
+        g.add_node(99);
+        let path = std::env::temp_dir().join("deepwalk_cache_round_trip.bin");
+        let path = path.to_str().unwrap();
+        write(&g, path, fingerprint()).unwrap();
+
+        let (cached, cached_fingerprint) = read(path).unwrap();
+        assert_eq!(cached_fingerprint, fingerprint());
+        assert_eq!(cached.node_ids_by_idx(), g.node_ids_by_idx());
+        for node in g.get_node_iter() {
+            assert_eq!(cached.get_node(&node.get_id()), Some(node));
+        }
+
+        let mut weighted = Graph::new();
+        weighted.build(vec![vec![1, 2], vec![1, 2], vec![2, 3], vec![3, 3]]);
+        weighted.apply_edge_policy(crate::graph::EdgePolicy {
+            duplicates: crate::config::DuplicateEdges::Sum,
+            self_loops: crate::config::SelfLoops::Keep,
+        });
+        write(&weighted, path, fingerprint()).unwrap();
+        let (cached, _) = read(path).unwrap();
+        for node in weighted.get_node_iter() {
+            assert_eq!(cached.get_node(&node.get_id()), Some(node));
+        }
+        assert_eq!(
+            cached.get_node(&1).unwrap().get_edge_weights(),
+            Some(&[2.0][..])
+        );
+
+        let mut bytes = fs::read(path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(path, &bytes).unwrap();
+        assert!(matches!(read(path), Err(DeepwalkError::Format(_))));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_load_or_build() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("deepwalk_cache_load_or_build.bin");
+        let path = path.to_str().unwrap();
+        let source = dir.join("deepwalk_cache_load_or_build.txt");
+        let source = source.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        fs::write(source, "1 2\n").unwrap();
+        let build = |edges: Vec<Vec<NodeID>>| {
+            move || {
+                let mut g = Graph::new();
+                g.build(edges);
+                Ok(g)
+            }
+        };
+
+        let g = load_or_build(path, source, "a", build(vec![vec![1, 2]])).unwrap();
+        assert_eq!(g.num_nodes(), 2);
+        let (_, cached) = read(path).unwrap();
+        assert_eq!(cached, Fingerprint::of_file(source, "a").unwrap());
+        // A matching cache is read without building.
+        let g = load_or_build(path, source, "a", || panic!("built")).unwrap();
+        assert_eq!(g.num_nodes(), 2);
+        // A touched but unchanged input is checksummed and still matches.
+        let touched = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(source)
+            .unwrap()
+            .set_modified(touched)
+            .unwrap();
+        let g = load_or_build(path, source, "a", || panic!("built")).unwrap();
+        assert_eq!(g.num_nodes(), 2);
+        // Other options or contents of the same length rebuild it.
+        let g = load_or_build(path, source, "b", build(vec![vec![1, 2], vec![2, 3]])).unwrap();
+        assert_eq!(g.num_nodes(), 3);
+        fs::write(source, "1 3\n").unwrap();
+        File::options()
+            .write(true)
+            .open(source)
+            .unwrap()
+            .set_modified(touched + std::time::Duration::from_secs(1))
+            .unwrap();
+        let g = load_or_build(path, source, "b", build(vec![vec![1, 3]])).unwrap();
+        assert_eq!(g.get_node(&3).unwrap().get_neighbors(), &[1]);
+        assert_eq!(
+            read(path).unwrap().1,
+            Fingerprint::of_file(source, "b").unwrap()
+        );
+        fs::remove_file(path).unwrap();
+        fs::remove_file(source).unwrap();
+    }
+
+    #[test]
+    fn test_write_failure() {
+        // The cache can't replace a directory, the graph is still built.
+        let dir = std::env::temp_dir().join("deepwalk_cache_write_failure");
+        let _ = fs::create_dir(&dir);
+        let path = dir.to_str().unwrap();
+        let build = || {
+            let mut g = Graph::new();
+            g.build(vec![vec![1, 2]]);
+            Ok(g)
+        };
+        let g = load_or_build(path, "karate_network.txt", "a", build).unwrap();
+        assert_eq!(g.num_nodes(), 2);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let a = Fingerprint::of_file("karate_network.txt", "edge_list").unwrap();
+        assert_eq!(
+            a.source_len,
+            fs::metadata("karate_network.txt").unwrap().len()
+        );
+        let b = Fingerprint::of_file("karate_network.txt", "adjacency_list").unwrap();
+        assert_eq!(a.source_crc, b.source_crc);
+        assert_ne!(a.options_crc, b.options_crc);
+        let stat = Fingerprint::stat("karate_network.txt", "edge_list").unwrap();
+        assert_eq!(stat.source_crc, 0);
+        assert_eq!(stat, Fingerprint { source_crc: 0, ..a });
+    }
+}
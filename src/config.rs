@@ -1,8 +1,235 @@
+use crate::error::DeepwalkError;
+use crate::input;
 use serde::Deserialize;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
+/// Where the leaf frequencies of the Huffman tree come from.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HuffmanWeights {
+    /// The degree of each node.
+    #[default]
+    Degree,
+    /// Visit counts in a corpus of walks generated before training.
+    Walks,
+    /// Counts read from `frequency_file`.
+    File,
+}
+
+/// The floating point type the model is trained in.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Precision {
+    F32,
+    #[default]
+    F64,
+}
+
+/// How the sigmoid is computed in the training steps.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SigmoidMode {
+    /// Call `exp` for every dot product.
+    Exact,
+    /// Look up a precomputed table and skip updates beyond `MAX_EXP`.
+    #[default]
+    Table,
+}
+
+/// Skip-gram predicts each context node from the center node, CBOW predicts
+/// the center node from its whole context window.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Architecture {
+    #[default]
+    SkipGram,
+    Cbow,
+}
+
+/// How DeepWalk samples its training walks.  `Restart` returns to the
+/// source with probability `alpha` before every step, like a personalized
+/// PageRank walk.  `App` pairs the source with the stopping nodes of walks
+/// that stop after every step with probability `alpha`, as in APP.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WalkMode {
+    #[default]
+    Uniform,
+    Restart,
+    App,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    #[default]
+    HierarchicalSoftmax,
+    NegativeSampling,
+}
+
+/// The embedding method, DeepWalk trains on random walks, LINE on edges
+/// sampled directly from the graph.  NetMF and Laplacian eigenmaps factorize
+/// dense matrices of the graph without training.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    #[default]
+    Deepwalk,
+    Line,
+    Netmf,
+    LaplacianEigenmaps,
+}
+
+/// Which LINE proximity is trained, `Both` trains the two separately and
+/// concatenates the normalized vectors.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LineOrder {
+    First,
+    #[default]
+    Second,
+    Both,
+}
+
+/// How input and context vectors are merged for `combined_file`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Combine {
+    #[default]
+    Sum,
+    Concat,
+}
+
+/// What early stopping tracks.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StoppingMetric {
+    #[default]
+    Off,
+    /// The training loss, smoothed over iterations.
+    Loss,
+    /// The link prediction AUC of edges held out of training.
+    LinkAuc,
+}
+
+/// The format of `metrics_file`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsFormat {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+/// How much is logged to stderr.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    /// Nothing but errors, which are always printed.
+    Quiet,
+    #[default]
+    Info,
+    Debug,
+}
+
+/// The file format of `input_file`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InputFormat {
+    /// By the extension, `.adj` or `.adjlist`, `.mtx`, `.graphml` or
+    /// `.gexf`, otherwise an edge list.
+    #[default]
+    Auto,
+    EdgeList,
+    /// A node followed by its neighbors on each line.
+    AdjacencyList,
+    /// A Matrix Market coordinate file.
+    MatrixMarket,
+    Graphml,
+    Gexf,
+}
+
+/// What happens to an edge that appears more than once.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateEdges {
+    /// Keep them as parallel edges, each one a step of the walk.
+    #[default]
+    Keep,
+    /// Keep one edge.
+    Merge,
+    /// Keep one edge weighted by the number of copies.
+    Sum,
+}
+
+/// What happens to edges from a node to itself.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfLoops {
+    #[default]
+    Keep,
+    Drop,
+}
+
+/// What happens to edge list lines that can't be parsed.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MalformedLines {
+    /// Fail on the first one.
+    #[default]
+    Strict,
+    /// Skip them and report the count after loading.
+    Skip,
+    /// Skip them and log the first few with their line numbers.
+    Warn,
+}
+
+fn default_negative() -> usize {
+    5
+}
+
+fn default_max_dense_nodes() -> usize {
+    10_000
+}
+
+fn default_comment() -> String {
+    "#".to_string()
+}
+
+fn default_columns() -> [usize; 2] {
+    [0, 1]
+}
+
+fn default_progress() -> bool {
+    true
+}
+
+fn default_patience() -> usize {
+    3
+}
+
+fn default_tolerance() -> f64 {
+    1e-4
+}
+
+fn default_eval_every() -> usize {
+    1
+}
+
+fn default_smoothing() -> f64 {
+    0.5
+}
+
+fn default_holdout_fraction() -> f64 {
+    0.1
+}
+
+fn default_alpha() -> f64 {
+    0.15
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     learning_rate: f64,
@@ -14,14 +241,193 @@ pub struct Config {
     perf_file: String,
     weight_file: String,
     nthreads: usize,
+    #[serde(default)]
+    sample: f64,
+    #[serde(default)]
+    huffman_weights: HuffmanWeights,
+    #[serde(default)]
+    frequency_file: String,
+    #[serde(default)]
+    max_code_length: usize,
+    #[serde(default)]
+    precision: Precision,
+    #[serde(default)]
+    sigmoid: SigmoidMode,
+    #[serde(default)]
+    architecture: Architecture,
+    #[serde(default)]
+    walk_mode: WalkMode,
+    #[serde(default = "default_alpha")]
+    alpha: f64,
+    #[serde(default)]
+    non_backtracking: bool,
+    #[serde(default)]
+    cbow_sum: bool,
+    #[serde(default)]
+    objective: Objective,
+    #[serde(default = "default_negative")]
+    negative: usize,
+    #[serde(default)]
+    method: Method,
+    #[serde(default = "default_max_dense_nodes")]
+    max_dense_nodes: usize,
+    #[serde(default)]
+    line_order: LineOrder,
+    #[serde(default)]
+    context_file: String,
+    #[serde(default)]
+    combined_file: String,
+    #[serde(default)]
+    combine: Combine,
+    #[serde(default)]
+    inner_node_file: String,
+    #[serde(default)]
+    early_stopping: StoppingMetric,
+    #[serde(default = "default_patience")]
+    patience: usize,
+    #[serde(default = "default_tolerance")]
+    tolerance: f64,
+    #[serde(default = "default_eval_every")]
+    eval_every: usize,
+    #[serde(default = "default_smoothing")]
+    smoothing: f64,
+    #[serde(default = "default_holdout_fraction")]
+    holdout_fraction: f64,
+    #[serde(default)]
+    metrics_file: String,
+    #[serde(default)]
+    metrics_format: MetricsFormat,
+    #[serde(default)]
+    metrics_every: usize,
+    #[serde(default)]
+    input_format: InputFormat,
+    #[serde(default)]
+    graph_cache: String,
+    #[serde(default)]
+    delimiter: Option<char>,
+    #[serde(default = "default_comment")]
+    comment: String,
+    #[serde(default)]
+    header: bool,
+    #[serde(default = "default_columns")]
+    columns: [usize; 2],
+    #[serde(default)]
+    malformed_lines: MalformedLines,
+    #[serde(default)]
+    duplicate_edges: DuplicateEdges,
+    #[serde(default)]
+    self_loops: SelfLoops,
+    #[serde(default)]
+    largest_component: bool,
+    #[serde(default)]
+    min_component_size: usize,
+    #[serde(default)]
+    component_column: bool,
+    #[serde(default)]
+    attribute_file: String,
+    #[serde(default)]
+    output_attributes: Vec<String>,
+    #[serde(default)]
+    log_level: LogLevel,
+    #[serde(default = "default_progress")]
+    progress: bool,
 }
 
 impl Config {
-    pub fn new<P: AsRef<Path>>(filename: &P) -> Config {
-        let file = File::open(filename).expect("Couldn't open config file");
+    pub fn new<P: AsRef<Path>>(filename: &P) -> Result<Config, DeepwalkError> {
+        let file = File::open(filename)?;
         let reader = BufReader::new(file);
-        let config: Config = serde_json::from_reader(reader).expect("Couldn't read config file");
-        config
+        let config: Config = serde_json::from_reader(reader)
+            .map_err(|e| DeepwalkError::Config(format!("couldn't read config file: {}", e)))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks for values that would make training undefined.
+    pub fn validate(&self) -> Result<(), DeepwalkError> {
+        if self.vector_dim == 0 {
+            return Err(DeepwalkError::Config(
+                "vector_dim must be at least 1".to_string(),
+            ));
+        }
+        if self.learning_rate.is_nan() || self.learning_rate <= 0.0 {
+            return Err(DeepwalkError::Config(
+                "learning_rate must be positive".to_string(),
+            ));
+        }
+        if self.sample < 0.0 {
+            return Err(DeepwalkError::Config(
+                "sample must not be negative".to_string(),
+            ));
+        }
+        if self.objective == Objective::NegativeSampling && self.negative == 0 {
+            return Err(DeepwalkError::Config(
+                "negative sampling needs at least 1 negative sample".to_string(),
+            ));
+        }
+        if self.huffman_weights == HuffmanWeights::File && self.frequency_file.is_empty() {
+            return Err(DeepwalkError::Config(
+                "huffman_weights is file but no frequency_file was given".to_string(),
+            ));
+        }
+        if self.walk_mode != WalkMode::Uniform && !(self.alpha > 0.0 && self.alpha <= 1.0) {
+            return Err(DeepwalkError::Config("alpha must be in (0, 1]".to_string()));
+        }
+        if self.early_stopping != StoppingMetric::Off {
+            if self.patience == 0 || self.eval_every == 0 {
+                return Err(DeepwalkError::Config(
+                    "patience and eval_every must be at least 1".to_string(),
+                ));
+            }
+            if !(self.smoothing > 0.0 && self.smoothing <= 1.0) {
+                return Err(DeepwalkError::Config(
+                    "smoothing must be in (0, 1]".to_string(),
+                ));
+            }
+            if !(self.holdout_fraction > 0.0 && self.holdout_fraction < 1.0) {
+                return Err(DeepwalkError::Config(
+                    "holdout_fraction must be in (0, 1)".to_string(),
+                ));
+            }
+        }
+        // LINE always uses negative sampling, but only the second order has
+        // context vectors.
+        let (has_context, hierarchical_softmax) = match self.method {
+            Method::Deepwalk => (
+                self.objective == Objective::NegativeSampling,
+                self.objective == Objective::HierarchicalSoftmax,
+            ),
+            Method::Line => (self.line_order == LineOrder::Second, false),
+            Method::Netmf | Method::LaplacianEigenmaps => (false, false),
+        };
+        let exports_context = !self.context_file.is_empty() || !self.combined_file.is_empty();
+        if !has_context && exports_context {
+            return Err(DeepwalkError::Config(
+                "context vectors only exist with negative_sampling or second order LINE"
+                    .to_string(),
+            ));
+        }
+        if !hierarchical_softmax && !self.inner_node_file.is_empty() {
+            return Err(DeepwalkError::Config(
+                "inner node vectors only exist with hierarchical_softmax".to_string(),
+            ));
+        }
+        if !self.output_attributes.is_empty() && self.attribute_file.is_empty() {
+            return Err(DeepwalkError::Config(
+                "output_attributes needs an attribute_file".to_string(),
+            ));
+        }
+        if !self.graph_cache.is_empty() && self.input_file == input::STDIN {
+            return Err(DeepwalkError::Config(
+                "graph_cache needs an input_file, stdin can't be checksummed".to_string(),
+            ));
+        }
+        if self.columns[0] == self.columns[1] {
+            return Err(DeepwalkError::Config(
+                "columns must name two different columns".to_string(),
+            ));
+        }
+        Ok(())
     }
 
     pub fn learning_rate(&self) -> f64 {
@@ -51,6 +457,151 @@ impl Config {
     pub fn nthreads(&self) -> usize {
         self.nthreads
     }
+    pub fn sample(&self) -> f64 {
+        self.sample
+    }
+    pub fn huffman_weights(&self) -> HuffmanWeights {
+        self.huffman_weights
+    }
+    pub fn frequency_file(&self) -> &str {
+        &self.frequency_file[..]
+    }
+    pub fn max_code_length(&self) -> usize {
+        self.max_code_length
+    }
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+    pub fn sigmoid(&self) -> SigmoidMode {
+        self.sigmoid
+    }
+    pub fn architecture(&self) -> Architecture {
+        self.architecture
+    }
+    pub fn walk_mode(&self) -> WalkMode {
+        self.walk_mode
+    }
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+    pub fn non_backtracking(&self) -> bool {
+        self.non_backtracking
+    }
+    pub fn cbow_sum(&self) -> bool {
+        self.cbow_sum
+    }
+    pub fn objective(&self) -> Objective {
+        self.objective
+    }
+    pub fn negative(&self) -> usize {
+        self.negative
+    }
+    pub fn method(&self) -> Method {
+        self.method
+    }
+    pub fn max_dense_nodes(&self) -> usize {
+        self.max_dense_nodes
+    }
+    pub fn line_order(&self) -> LineOrder {
+        self.line_order
+    }
+    pub fn context_file(&self) -> &str {
+        &self.context_file[..]
+    }
+    pub fn combined_file(&self) -> &str {
+        &self.combined_file[..]
+    }
+    pub fn combine(&self) -> Combine {
+        self.combine
+    }
+    pub fn inner_node_file(&self) -> &str {
+        &self.inner_node_file[..]
+    }
+    pub fn early_stopping(&self) -> StoppingMetric {
+        self.early_stopping
+    }
+    pub fn patience(&self) -> usize {
+        self.patience
+    }
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+    pub fn eval_every(&self) -> usize {
+        self.eval_every
+    }
+    pub fn smoothing(&self) -> f64 {
+        self.smoothing
+    }
+    pub fn holdout_fraction(&self) -> f64 {
+        self.holdout_fraction
+    }
+    pub fn metrics_file(&self) -> &str {
+        &self.metrics_file[..]
+    }
+    pub fn metrics_format(&self) -> MetricsFormat {
+        self.metrics_format
+    }
+    pub fn metrics_every(&self) -> usize {
+        self.metrics_every
+    }
+    pub fn input_format(&self) -> InputFormat {
+        self.input_format
+    }
+    /// A binary cache of the built graph, none if empty.
+    pub fn graph_cache(&self) -> &str {
+        &self.graph_cache[..]
+    }
+    /// The column delimiter, `None` for any run of whitespace.
+    pub fn delimiter(&self) -> Option<char> {
+        self.delimiter
+    }
+    /// Lines starting with this prefix are skipped, none if empty.
+    pub fn comment(&self) -> &str {
+        &self.comment[..]
+    }
+    pub fn header(&self) -> bool {
+        self.header
+    }
+    /// The zero based source and target columns.
+    pub fn columns(&self) -> [usize; 2] {
+        self.columns
+    }
+    pub fn malformed_lines(&self) -> MalformedLines {
+        self.malformed_lines
+    }
+    pub fn duplicate_edges(&self) -> DuplicateEdges {
+        self.duplicate_edges
+    }
+    pub fn self_loops(&self) -> SelfLoops {
+        self.self_loops
+    }
+    pub fn largest_component(&self) -> bool {
+        self.largest_component
+    }
+    pub fn min_component_size(&self) -> usize {
+        self.min_component_size
+    }
+    pub fn component_column(&self) -> bool {
+        self.component_column
+    }
+    pub fn attribute_file(&self) -> &str {
+        &self.attribute_file
+    }
+    pub fn output_attributes(&self) -> &[String] {
+        &self.output_attributes
+    }
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+    pub fn progress(&self) -> bool {
+        self.progress
+    }
+    /// Whether any of the output vector files is requested.
+    pub fn exports_output_vectors(&self) -> bool {
+        !(self.context_file.is_empty()
+            && self.combined_file.is_empty()
+            && self.inner_node_file.is_empty())
+    }
 }
 
 #[cfg(test)]
@@ -58,6 +609,7 @@ mod config_tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::redundant_slicing)]
     fn test_config() {
         let file = r#"{
             "learning_rate": 0.025,
@@ -80,5 +632,232 @@ mod config_tests {
         assert_eq!(config.perf_file(), &"perf.txt"[..]);
         assert_eq!(config.weight_file(), &"weights.txt"[..]);
         assert_eq!(config.nthreads, 0);
+        assert_eq!(config.sample(), 0.0);
+        assert_eq!(config.huffman_weights(), HuffmanWeights::Degree);
+        assert_eq!(config.max_code_length(), 0);
+        assert_eq!(config.precision(), Precision::F64);
+        assert_eq!(config.sigmoid(), SigmoidMode::Table);
+        assert_eq!(config.architecture(), Architecture::SkipGram);
+        assert_eq!(config.walk_mode(), WalkMode::Uniform);
+        assert_eq!(config.alpha(), 0.15);
+        assert!(!config.non_backtracking());
+        assert!(!config.cbow_sum());
+        assert_eq!(config.objective(), Objective::HierarchicalSoftmax);
+        assert_eq!(config.negative(), 5);
+        assert_eq!(config.combine(), Combine::Sum);
+        assert!(!config.exports_output_vectors());
+        assert_eq!(config.method(), Method::Deepwalk);
+        assert_eq!(config.max_dense_nodes(), 10_000);
+        assert_eq!(config.line_order(), LineOrder::Second);
+        assert_eq!(config.early_stopping(), StoppingMetric::Off);
+        assert_eq!(config.patience(), 3);
+        assert_eq!(config.eval_every(), 1);
+        assert_eq!(config.metrics_file(), "");
+        assert_eq!(config.metrics_format(), MetricsFormat::Jsonl);
+        assert_eq!(config.metrics_every(), 0);
+        assert_eq!(config.input_format(), InputFormat::Auto);
+        assert_eq!(config.graph_cache(), "");
+        assert_eq!(config.delimiter(), None);
+        assert_eq!(config.comment(), "#");
+        assert!(!config.header());
+        assert_eq!(config.columns(), [0, 1]);
+        assert_eq!(config.malformed_lines(), MalformedLines::Strict);
+        assert_eq!(config.duplicate_edges(), DuplicateEdges::Keep);
+        assert_eq!(config.self_loops(), SelfLoops::Keep);
+        assert!(!config.largest_component());
+        assert_eq!(config.min_component_size(), 0);
+        assert!(!config.component_column());
+        assert_eq!(config.attribute_file(), "");
+        assert!(config.output_attributes().is_empty());
+        assert_eq!(config.log_level(), LogLevel::Info);
+        assert!(config.progress());
+    }
+
+    #[test]
+    fn test_huffman_weights() {
+        let file = r#"{
+            "learning_rate": 0.025,
+            "vector_dim": 128,
+            "walk_length": 10,
+            "window_size": 2,
+            "num_iterations": 25,
+            "input_file": "karate_network.txt",
+            "perf_file": "perf.txt",
+            "weight_file": "weights.txt",
+            "nthreads": 0,
+            "huffman_weights": "file",
+            "frequency_file": "counts.txt",
+            "max_code_length": 40,
+            "precision": "f32"
+        }"#;
+
+        let config: Config = serde_json::from_str(file).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.huffman_weights(), HuffmanWeights::File);
+        assert_eq!(config.frequency_file(), "counts.txt");
+        assert_eq!(config.max_code_length(), 40);
+        assert_eq!(config.precision(), Precision::F32);
+    }
+
+    #[test]
+    fn test_validate() {
+        let file = r#"{
+            "learning_rate": 0.025,
+            "vector_dim": 0,
+            "walk_length": 10,
+            "window_size": 2,
+            "num_iterations": 25,
+            "input_file": "karate_network.txt",
+            "perf_file": "perf.txt",
+            "weight_file": "weights.txt",
+            "nthreads": 0
+        }"#;
+
+        let mut config: Config = serde_json::from_str(file).unwrap();
+        assert!(config.validate().is_err());
+
+        config.vector_dim = 2;
+        config.walk_mode = WalkMode::App;
+        assert!(config.validate().is_ok());
+        config.alpha = 0.0;
+        assert!(config.validate().is_err());
+        config.walk_mode = WalkMode::Uniform;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_edge_list_format() {
+        let file = r#"{
+            "learning_rate": 0.025,
+            "vector_dim": 128,
+            "walk_length": 10,
+            "window_size": 2,
+            "num_iterations": 25,
+            "input_file": "lastfm_asia_edges.csv",
+            "perf_file": "perf.txt",
+            "weight_file": "weights.txt",
+            "nthreads": 0,
+            "delimiter": ",",
+            "comment": "%",
+            "header": true,
+            "columns": [1, 0],
+            "malformed_lines": "warn",
+            "input_format": "adjacency_list",
+            "duplicate_edges": "sum",
+            "self_loops": "drop",
+            "largest_component": true,
+            "min_component_size": 10,
+            "component_column": true,
+            "attribute_file": "lastfm_asia_target.csv",
+            "output_attributes": ["target"]
+        }"#;
+
+        let mut config: Config = serde_json::from_str(file).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.delimiter(), Some(','));
+        assert_eq!(config.comment(), "%");
+        assert!(config.header());
+        assert_eq!(config.columns(), [1, 0]);
+        assert_eq!(config.malformed_lines(), MalformedLines::Warn);
+        assert_eq!(config.input_format(), InputFormat::AdjacencyList);
+        assert_eq!(config.duplicate_edges(), DuplicateEdges::Sum);
+        assert_eq!(config.self_loops(), SelfLoops::Drop);
+        assert!(config.largest_component());
+        assert_eq!(config.min_component_size(), 10);
+        assert!(config.component_column());
+        assert_eq!(config.attribute_file(), "lastfm_asia_target.csv");
+        assert_eq!(config.output_attributes(), &["target"]);
+
+        config.columns = [2, 2];
+        assert!(config.validate().is_err());
+        config.columns = [0, 1];
+        config.input_file = "-".to_string();
+        assert!(config.validate().is_ok());
+        config.graph_cache = "graph.cache".to_string();
+        assert!(config.validate().is_err());
+        config.graph_cache = String::new();
+        config.attribute_file = String::new();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_early_stopping() {
+        let file = r#"{
+            "learning_rate": 0.025,
+            "vector_dim": 128,
+            "walk_length": 10,
+            "window_size": 2,
+            "num_iterations": 25,
+            "input_file": "karate_network.txt",
+            "perf_file": "perf.txt",
+            "weight_file": "weights.txt",
+            "nthreads": 0,
+            "early_stopping": "link_auc",
+            "patience": 5,
+            "tolerance": 0.001,
+            "eval_every": 2,
+            "holdout_fraction": 0.2
+        }"#;
+
+        let mut config: Config = serde_json::from_str(file).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.early_stopping(), StoppingMetric::LinkAuc);
+        assert_eq!(config.patience(), 5);
+        assert_eq!(config.tolerance(), 0.001);
+        assert_eq!(config.eval_every(), 2);
+        assert_eq!(config.smoothing(), 0.5);
+        assert_eq!(config.holdout_fraction(), 0.2);
+
+        config.holdout_fraction = 1.0;
+        assert!(config.validate().is_err());
+        config.holdout_fraction = 0.2;
+        config.patience = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_output_vector_files() {
+        let file = r#"{
+            "learning_rate": 0.025,
+            "vector_dim": 128,
+            "walk_length": 10,
+            "window_size": 2,
+            "num_iterations": 25,
+            "input_file": "karate_network.txt",
+            "perf_file": "perf.txt",
+            "weight_file": "weights.txt",
+            "nthreads": 0,
+            "objective": "negative_sampling",
+            "context_file": "context.txt",
+            "combined_file": "combined.txt",
+            "combine": "concat"
+        }"#;
+
+        let mut config: Config = serde_json::from_str(file).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.exports_output_vectors());
+        assert_eq!(config.context_file(), "context.txt");
+        assert_eq!(config.combine(), Combine::Concat);
+
+        config.inner_node_file = "inner.txt".to_string();
+        assert!(config.validate().is_err());
+        config.objective = Objective::HierarchicalSoftmax;
+        assert!(config.validate().is_err());
+        config.context_file.clear();
+        config.combined_file.clear();
+        assert!(config.validate().is_ok());
+        config.method = Method::Line;
+        assert!(config.validate().is_err());
+
+        config.inner_node_file.clear();
+        config.context_file = "context.txt".to_string();
+        assert!(config.validate().is_ok());
+        config.line_order = LineOrder::Both;
+        assert!(config.validate().is_err());
+        config.context_file.clear();
+        config.method = Method::Netmf;
+        assert!(config.validate().is_ok());
+        config.inner_node_file = "inner.txt".to_string();
+        assert!(config.validate().is_err());
     }
 }
@@ -0,0 +1,293 @@
+//! Early stopping on the smoothed training loss or on the link prediction
+//! AUC of edges held out of the training graph.
+
+use crate::config::{Config, StoppingMetric};
+use crate::float::Float;
+use crate::graph::Graph;
+use nalgebra::DMatrix;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashSet;
+
+/// The outcome of `EarlyStopping::evaluate` for one iteration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Evaluation {
+    /// Not an evaluation iteration.
+    Skipped,
+    /// The score beat the best score by more than the tolerance.
+    Improved(f64),
+    NotImproved(f64),
+    /// `patience` evaluations in a row did not improve.
+    Stop(f64),
+}
+
+impl Evaluation {
+    /// The value for the perf file, the smoothed loss or the AUC.
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            Evaluation::Skipped => None,
+            Evaluation::Improved(v) | Evaluation::NotImproved(v) | Evaluation::Stop(v) => Some(*v),
+        }
+    }
+}
+
+/// Edges removed from the training graph and as many node pairs that are
+/// not edges, as node indices.
+#[derive(Debug)]
+pub struct LinkHoldout {
+    positives: Vec<(usize, usize)>,
+    negatives: Vec<(usize, usize)>,
+}
+
+impl LinkHoldout {
+    /// Removes about `fraction` of the edges from `graph`.  Self loops and
+    /// edges whose removal would isolate a node are kept.
+    pub fn split<R: Rng>(graph: &mut Graph, fraction: f64, rng: &mut R) -> LinkHoldout {
+        let mut edges = Vec::new();
+        let mut adjacent = HashSet::new();
+        for node in graph.get_node_iter() {
+            let a = *graph.get_node_idx(&node.get_id()).unwrap();
+            for neighbor in node.get_neighbors() {
+                let b = *graph.get_node_idx(neighbor).unwrap();
+                adjacent.insert((a, b));
+                if a < b {
+                    edges.push((node.get_id(), *neighbor));
+                }
+            }
+        }
+        edges.sort();
+        edges.shuffle(rng);
+
+        let target = (edges.len() as f64 * fraction).round() as usize;
+        let mut positives = Vec::with_capacity(target);
+        for (a, b) in edges {
+            if positives.len() == target {
+                break;
+            }
+            let degree = |id| graph.get_node(&id).unwrap().get_weight();
+            if degree(a) > 1 && degree(b) > 1 {
+                graph.remove_edge(a, b);
+                positives.push((
+                    *graph.get_node_idx(&a).unwrap(),
+                    *graph.get_node_idx(&b).unwrap(),
+                ));
+            }
+        }
+
+        let n = graph.num_nodes();
+        let edge_pairs = adjacent.iter().filter(|(a, b)| a != b).count() / 2;
+        let max_negatives = (n * n.saturating_sub(1) / 2).saturating_sub(edge_pairs);
+        let mut negatives = Vec::with_capacity(positives.len());
+        while negatives.len() < positives.len().min(max_negatives) {
+            let (a, b) = (rng.gen_range(0, n), rng.gen_range(0, n));
+            if a != b && !adjacent.contains(&(a, b)) {
+                negatives.push((a, b));
+            }
+        }
+        LinkHoldout {
+            positives,
+            negatives,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positives.is_empty()
+    }
+
+    /// The AUC of cosine similarity between input vectors separating the
+    /// held out edges from the non edges.
+    pub fn auc<T: Float>(&self, input: &DMatrix<T>) -> f64 {
+        let cosine = |(a, b): &(usize, usize)| {
+            let (x, y) = (input.column(*a), input.column(*b));
+            let norms = x.norm() * y.norm();
+            if norms > T::zero() {
+                (x.dot(&y) / norms).as_f64()
+            } else {
+                0.0
+            }
+        };
+        let positive: Vec<f64> = self.positives.iter().map(cosine).collect();
+        let negative: Vec<f64> = self.negatives.iter().map(cosine).collect();
+        if positive.is_empty() || negative.is_empty() {
+            return 0.5;
+        }
+        let mut wins = 0.0;
+        for p in &positive {
+            for n in &negative {
+                if p > n {
+                    wins += 1.0;
+                } else if p == n {
+                    wins += 0.5;
+                }
+            }
+        }
+        wins / (positive.len() * negative.len()) as f64
+    }
+}
+
+/// Tracks a score where higher is better, the negated smoothed loss or the
+/// held out AUC, and decides when to stop.
+#[derive(Debug)]
+pub struct EarlyStopping {
+    metric: StoppingMetric,
+    holdout: Option<LinkHoldout>,
+    patience: usize,
+    tolerance: f64,
+    eval_every: usize,
+    smoothing: f64,
+    smoothed_loss: Option<f64>,
+    best: Option<f64>,
+    best_iteration: Option<usize>,
+    bad_evaluations: usize,
+}
+
+impl EarlyStopping {
+    /// `None` if early stopping is off.  With `link_auc` the held out edges
+    /// are removed from `graph`.
+    pub fn from_config<R: Rng>(
+        config: &Config,
+        graph: &mut Graph,
+        rng: &mut R,
+    ) -> Option<EarlyStopping> {
+        let holdout = match config.early_stopping() {
+            StoppingMetric::Off => return None,
+            StoppingMetric::Loss => None,
+            StoppingMetric::LinkAuc => {
+                Some(LinkHoldout::split(graph, config.holdout_fraction(), rng))
+            }
+        };
+        Some(EarlyStopping {
+            metric: config.early_stopping(),
+            holdout,
+            patience: config.patience(),
+            tolerance: config.tolerance(),
+            eval_every: config.eval_every(),
+            smoothing: config.smoothing(),
+            smoothed_loss: None,
+            best: None,
+            best_iteration: None,
+            bad_evaluations: 0,
+        })
+    }
+
+    /// Records iteration `iter` with average loss `loss`.  `input` is only
+    /// called for held out AUC evaluations.
+    pub fn evaluate<T, F>(&mut self, iter: usize, loss: f64, input: F) -> Evaluation
+    where
+        T: Float,
+        F: FnOnce() -> DMatrix<T>,
+    {
+        let smoothed = match self.smoothed_loss {
+            Some(s) => self.smoothing * loss + (1.0 - self.smoothing) * s,
+            None => loss,
+        };
+        self.smoothed_loss = Some(smoothed);
+        if !(iter + 1).is_multiple_of(self.eval_every) {
+            return Evaluation::Skipped;
+        }
+
+        let (score, value) = match (&self.metric, &self.holdout) {
+            (StoppingMetric::LinkAuc, Some(holdout)) => {
+                let auc = holdout.auc(&input());
+                (auc, auc)
+            }
+            _ => (-smoothed, smoothed),
+        };
+        match self.best {
+            Some(best) if score <= best + self.tolerance => {
+                self.bad_evaluations += 1;
+                if self.bad_evaluations >= self.patience {
+                    Evaluation::Stop(value)
+                } else {
+                    Evaluation::NotImproved(value)
+                }
+            }
+            _ => {
+                self.best = Some(score);
+                self.best_iteration = Some(iter);
+                self.bad_evaluations = 0;
+                Evaluation::Improved(value)
+            }
+        }
+    }
+
+    pub fn best_iteration(&self) -> Option<usize> {
+        self.best_iteration
+    }
+
+    pub fn holdout(&self) -> Option<&LinkHoldout> {
+        self.holdout.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod early_stopping_tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn controller(metric: StoppingMetric, patience: usize, eval_every: usize) -> EarlyStopping {
+        EarlyStopping {
+            metric,
+            holdout: None,
+            patience,
+            tolerance: 0.01,
+            eval_every,
+            smoothing: 1.0,
+            smoothed_loss: None,
+            best: None,
+            best_iteration: None,
+            bad_evaluations: 0,
+        }
+    }
+
+    #[test]
+    fn test_patience() {
+        let mut es = controller(StoppingMetric::Loss, 2, 1);
+        let input = || DMatrix::<f64>::zeros(1, 1);
+        assert_eq!(es.evaluate(0, 3.0, input), Evaluation::Improved(3.0));
+        assert_eq!(es.evaluate(1, 2.0, input), Evaluation::Improved(2.0));
+        // Within the tolerance.
+        assert_eq!(es.evaluate(2, 1.995, input), Evaluation::NotImproved(1.995));
+        assert_eq!(es.evaluate(3, 1.0, input), Evaluation::Improved(1.0));
+        assert_eq!(es.evaluate(4, 1.5, input), Evaluation::NotImproved(1.5));
+        assert_eq!(es.evaluate(5, 1.5, input), Evaluation::Stop(1.5));
+        assert_eq!(es.best_iteration(), Some(3));
+    }
+
+    #[test]
+    fn test_smoothing_and_eval_every() {
+        let mut es = controller(StoppingMetric::Loss, 1, 2);
+        es.smoothing = 0.5;
+        let input = || DMatrix::<f64>::zeros(1, 1);
+        assert_eq!(es.evaluate(0, 4.0, input), Evaluation::Skipped);
+        assert_eq!(es.evaluate(1, 2.0, input), Evaluation::Improved(3.0));
+        assert_eq!(es.evaluate(2, 2.0, input), Evaluation::Skipped);
+        assert_eq!(es.evaluate(3, 4.0, input), Evaluation::Stop(3.25));
+    }
+
+    #[test]
+    fn test_link_holdout() {
+        let mut g = Graph::new();
+        g.build_graph_from_file("karate_network.txt").unwrap();
+        let holdout = LinkHoldout::split(&mut g, 0.2, &mut thread_rng());
+        assert_eq!(holdout.len(), 16);
+        assert_eq!(holdout.negatives.len(), 16);
+        assert_eq!(g.num_nodes(), 34);
+        assert!(g.get_node_iter().all(|n| n.get_weight() > 0));
+        let edges: usize = g.get_node_iter().map(|n| n.get_neighbors().len()).sum();
+        assert_eq!(edges, 2 * (78 - 16));
+
+        assert_eq!(holdout.auc(&DMatrix::<f64>::zeros(2, 34)), 0.5);
+
+        let holdout = LinkHoldout {
+            positives: vec![(0, 1)],
+            negatives: vec![(0, 2)],
+        };
+        let input = DMatrix::from_row_slice(2, 3, &[1.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(holdout.auc(&input), 1.0);
+    }
+}
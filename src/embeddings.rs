@@ -0,0 +1,149 @@
+//! Writing vectors in the weight file format, one `id v1 v2 ...` line per
+//! column of a matrix.
+
+use crate::config::{Combine, Config};
+use crate::float::Float;
+use crate::graph::Graph;
+use crate::output_layer::OutputLayer;
+use nalgebra::DMatrix;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes column `i` of `mat` on a line starting with `ids[i]`.
+pub fn write_vectors<P, D, T>(path: &P, ids: &[D], mat: &DMatrix<T>) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    D: Display,
+    T: Float,
+{
+    assert_eq!(ids.len(), mat.ncols());
+    let mut f = BufWriter::new(File::create(path)?);
+    for (id, column) in ids.iter().zip(mat.column_iter()) {
+        write!(f, "{}", id)?;
+        for x in column.iter() {
+            write!(f, " {}", x)?;
+        }
+        writeln!(f)?;
+    }
+    f.flush()
+}
+
+/// The leading columns of a node's line, by node index: its id, followed
+/// by its component with `component_column` and the `output_attributes`.
+/// Missing attributes are written as `-` and whitespace in values as `_`,
+/// so every line has the same number of columns.
+pub fn node_labels(graph: &Graph, config: &Config) -> Vec<String> {
+    let components = if config.component_column() {
+        graph.component_labels()
+    } else {
+        Vec::new()
+    };
+    let attributes = graph.attributes();
+    let columns: Vec<usize> = config
+        .output_attributes()
+        .iter()
+        .filter_map(|name| attributes.column(name))
+        .collect();
+    graph
+        .node_ids_by_idx()
+        .into_iter()
+        .enumerate()
+        .map(|(idx, id)| {
+            let mut label = id.to_string();
+            if let Some(component) = components.get(idx) {
+                label += &format!(" {}", component);
+            }
+            for column in &columns {
+                let value = attributes.get(id, *column).unwrap_or("-");
+                label.push(' ');
+                label.extend(
+                    value
+                        .chars()
+                        .map(|c| if c.is_whitespace() { '_' } else { c }),
+                );
+            }
+            label
+        })
+        .collect()
+}
+
+/// Merges input and context vectors column by column.
+pub fn combine<T: Float>(input: &DMatrix<T>, context: &DMatrix<T>, combine: Combine) -> DMatrix<T> {
+    assert_eq!(input.shape(), context.shape());
+    match combine {
+        Combine::Sum => input + context,
+        Combine::Concat => {
+            let dim = input.nrows();
+            DMatrix::from_fn(2 * dim, input.ncols(), |r, c| {
+                if r < dim {
+                    input[(r, c)]
+                } else {
+                    context[(r - dim, c)]
+                }
+            })
+        }
+    }
+}
+
+/// Writes the context, combined and inner node vectors requested in
+/// `config`.  Context vectors are the output vectors of negative sampling,
+/// the output vectors of hierarchical softmax belong to inner nodes of the
+/// Huffman tree and are written with their tree index.  Node vectors start
+/// with their `labels`, see `node_labels`.
+pub fn write_output_vectors<T: Float>(
+    input: &DMatrix<T>,
+    output: &DMatrix<T>,
+    output_layer: &OutputLayer,
+    labels: &[String],
+    config: &Config,
+) -> io::Result<()> {
+    match output_layer {
+        OutputLayer::HierarchicalSoftmax(_) => {
+            if !config.inner_node_file().is_empty() {
+                let tree_indices: Vec<usize> = (0..output.ncols()).collect();
+                write_vectors(&config.inner_node_file(), &tree_indices, output)?;
+            }
+        }
+        OutputLayer::NegativeSampling { .. } => {
+            if !config.context_file().is_empty() {
+                write_vectors(&config.context_file(), labels, output)?;
+            }
+            if !config.combined_file().is_empty() {
+                let combined = combine(input, output, config.combine());
+                write_vectors(&config.combined_file(), labels, &combined)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod embeddings_tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_combine() {
+        let input = DMatrix::from_row_slice(2, 2, &[1.0, 2.0, 3.0, 4.0]);
+        let context = DMatrix::from_row_slice(2, 2, &[10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(
+            combine(&input, &context, Combine::Sum),
+            DMatrix::from_row_slice(2, 2, &[11.0, 22.0, 33.0, 44.0])
+        );
+        assert_eq!(
+            combine(&input, &context, Combine::Concat),
+            DMatrix::from_row_slice(4, 2, &[1.0, 2.0, 3.0, 4.0, 10.0, 20.0, 30.0, 40.0])
+        );
+    }
+
+    #[test]
+    fn test_write_vectors() {
+        let path = env::temp_dir().join("embeddings_write_vectors.txt");
+        let mat = DMatrix::from_row_slice(2, 2, &[1.0f32, 2.0, 3.0, 4.5]);
+        write_vectors(&path, &[7, 9], &mat).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "7 1 3\n9 2 4.5\n");
+    }
+}
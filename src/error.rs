@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum DeepwalkError {
+    Io(io::Error),
+    Config(String),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// An input file that is not line based is malformed.
+    Format(String),
+    EmptyGraph,
+}
+
+impl fmt::Display for DeepwalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeepwalkError::Io(e) => write!(f, "{}", e),
+            DeepwalkError::Config(message) => write!(f, "Invalid config: {}", message),
+            DeepwalkError::Parse { line, message } => {
+                write!(f, "Could not parse line {}: {}", line, message)
+            }
+            DeepwalkError::Format(message) => write!(f, "Invalid input file: {}", message),
+            DeepwalkError::EmptyGraph => write!(f, "The input graph has no nodes"),
+        }
+    }
+}
+
+impl Error for DeepwalkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DeepwalkError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DeepwalkError {
+    fn from(e: io::Error) -> DeepwalkError {
+        DeepwalkError::Io(e)
+    }
+}
@@ -0,0 +1,43 @@
+use crate::kernels;
+use nalgebra::RealField;
+use std::fmt::Display;
+
+/// The floating point types the models can be trained in.
+pub trait Float: RealField + Copy + Display + Send + Sync + 'static {
+    fn cast(x: f64) -> Self;
+    fn as_f64(self) -> f64;
+    /// Dot product of two equally long slices.
+    fn dot(a: &[Self], b: &[Self]) -> Self;
+    /// `y += alpha * x`
+    fn axpy(alpha: Self, x: &[Self], y: &mut [Self]);
+}
+
+impl Float for f32 {
+    fn cast(x: f64) -> f32 {
+        x as f32
+    }
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        kernels::dot_f32(a, b)
+    }
+    fn axpy(alpha: f32, x: &[f32], y: &mut [f32]) {
+        kernels::axpy_f32(alpha, x, y)
+    }
+}
+
+impl Float for f64 {
+    fn cast(x: f64) -> f64 {
+        x
+    }
+    fn as_f64(self) -> f64 {
+        self
+    }
+    fn dot(a: &[f64], b: &[f64]) -> f64 {
+        kernels::dot_f64(a, b)
+    }
+    fn axpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+        kernels::axpy_f64(alpha, x, y)
+    }
+}
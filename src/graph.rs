@@ -1,15 +1,27 @@
+use crate::attributes::NodeAttributes;
+use crate::config::{Config, DuplicateEdges, SelfLoops};
+use crate::error::DeepwalkError;
+use crate::input;
+use crate::loader::{self, parse_token, EdgeListFormat};
+use crate::stats::{self, GraphStats};
 use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cmp;
 use std::collections::hash_map;
 use std::collections::HashMap;
-use std::fs;
+use std::io::BufRead;
 
-type NodeID = i32;
+pub type NodeID = i32;
 
 #[derive(Debug, PartialEq)]
 pub struct GraphNode {
     node_id: NodeID,
     edge_list: Vec<NodeID>,
     num_edges: i32,
+    /// Parallel to `edge_list`, empty if every edge has weight 1.
+    edge_weights: Vec<f32>,
+    /// Prefix sums of `edge_weights` for weighted steps.
+    cumulative_weights: Vec<f64>,
 }
 
 impl GraphNode {
@@ -18,31 +30,163 @@ impl GraphNode {
             node_id,
             edge_list: Vec::new(),
             num_edges: 0,
+            edge_weights: Vec::new(),
+            cumulative_weights: Vec::new(),
         }
     }
 
+    /// Adds an edge, of weight 1 if the node is weighted.
     pub fn add_edge(&mut self, target: NodeID) {
         self.edge_list.push(target);
         self.num_edges += 1;
+        if !self.edge_weights.is_empty() {
+            self.edge_weights.push(1.0);
+            self.update_cumulative_weights(self.edge_weights.len() - 1);
+        }
     }
 
+    /// Removes one edge to `target`, returns false if there is none.
+    pub fn remove_edge(&mut self, target: NodeID) -> bool {
+        match self.edge_list.iter().position(|n| *n == target) {
+            Some(pos) => {
+                self.edge_list.swap_remove(pos);
+                self.num_edges -= 1;
+                if !self.edge_weights.is_empty() {
+                    self.edge_weights.swap_remove(pos);
+                    self.update_cumulative_weights(pos);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The number of edges.
     pub fn get_weight(&self) -> i32 {
         self.num_edges
     }
 
+    /// The sum of the edge weights, the degree if unweighted.
+    pub fn get_weighted_degree(&self) -> f64 {
+        match self.cumulative_weights.last() {
+            Some(total) => *total,
+            None => self.num_edges as f64,
+        }
+    }
+
+    /// The weight of each edge in `get_neighbors` order, `None` if every
+    /// edge has weight 1.
+    pub fn get_edge_weights(&self) -> Option<&[f32]> {
+        if self.edge_weights.is_empty() {
+            None
+        } else {
+            Some(&self.edge_weights)
+        }
+    }
+
+    /// Sets the weight of each edge, an empty `weights` makes the node
+    /// unweighted.
+    pub fn set_edge_weights(&mut self, weights: Vec<f32>) {
+        assert!(weights.is_empty() || weights.len() == self.edge_list.len());
+        self.edge_weights = weights;
+        self.update_cumulative_weights(0);
+    }
+
+    /// Recomputes the prefix sums from edge `start` on, the ones before it
+    /// are unchanged, so appending an edge costs O(1) rather than O(degree).
+    fn update_cumulative_weights(&mut self, start: usize) {
+        self.cumulative_weights.truncate(start);
+        let mut total = self.cumulative_weights.last().copied().unwrap_or(0.0);
+        for w in &self.edge_weights[start..] {
+            total += *w as f64;
+            self.cumulative_weights.push(total);
+        }
+    }
+
     pub fn get_id(&self) -> NodeID {
         self.node_id
     }
 
+    pub fn get_neighbors(&self) -> &[NodeID] {
+        &self.edge_list
+    }
+
+    /// A neighbor chosen proportionally to the edge weights.
     pub fn random_step(&self) -> Option<&NodeID> {
-        self.edge_list.choose(&mut rand::thread_rng())
+        let mut rng = rand::thread_rng();
+        match self.cumulative_weights.last() {
+            Some(total) if *total > 0.0 => {
+                let x = rng.gen::<f64>() * total;
+                let i = self.cumulative_weights.partition_point(|c| *c <= x);
+                self.edge_list.get(i.min(self.edge_list.len() - 1))
+            }
+            _ => self.edge_list.choose(&mut rng),
+        }
+    }
+
+    /// A neighbor other than `previous` chosen proportionally to the edge
+    /// weights, or `previous` if no other neighbor can be reached.
+    pub fn non_backtracking_step(&self, previous: NodeID) -> Option<&NodeID> {
+        let next = self.random_step()?;
+        if *next != previous {
+            return Some(next);
+        }
+        // Draw again from the other edges only.
+        let weighted = self.cumulative_weights.last().is_some_and(|t| *t > 0.0);
+        let weight = |i: usize| {
+            if weighted {
+                self.edge_weights[i] as f64
+            } else {
+                1.0
+            }
+        };
+        let others = || (0..self.edge_list.len()).filter(|i| self.edge_list[*i] != previous);
+        let total: f64 = others().map(weight).sum();
+        if total <= 0.0 {
+            return Some(next);
+        }
+        let mut x = rand::thread_rng().gen::<f64>() * total;
+        let mut last = None;
+        for i in others() {
+            last = self.edge_list.get(i);
+            x -= weight(i);
+            if x < 0.0 {
+                break;
+            }
+        }
+        last
     }
 }
 
-#[derive(Debug)]
+/// What happens to repeated edges and self loops after loading.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EdgePolicy {
+    pub duplicates: DuplicateEdges,
+    pub self_loops: SelfLoops,
+}
+
+impl EdgePolicy {
+    pub fn from_config(config: &Config) -> EdgePolicy {
+        EdgePolicy {
+            duplicates: config.duplicate_edges(),
+            self_loops: config.self_loops(),
+        }
+    }
+}
+
+/// Duplicate edges and self loops seen by `Graph::apply_edge_policy`.
+/// Every repetition of an undirected edge counts once.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EdgeCounts {
+    pub duplicates: usize,
+    pub self_loops: usize,
+}
+
+#[derive(Debug, Default)]
 pub struct Graph {
     nodes: HashMap<NodeID, GraphNode>,
     node_to_idx_map: HashMap<NodeID, usize>,
+    attributes: NodeAttributes,
 }
 
 impl Graph {
@@ -52,78 +196,313 @@ impl Graph {
         Graph {
             nodes,
             node_to_idx_map,
+            attributes: NodeAttributes::default(),
         }
     }
 
     pub fn build(&mut self, edge_list: Vec<Vec<NodeID>>) {
         for edge in edge_list {
-            let node1 = edge[0];
-            let node2 = edge[1];
+            self.add_edge(edge[0], edge[1]);
+        }
+    }
 
-            self.node_to_idx_map
-                .entry(node1)
-                .or_insert(self.nodes.len());
-            self.nodes.entry(node1).or_insert(GraphNode::new(node1));
-            self.node_to_idx_map
-                .entry(node2)
-                .or_insert(self.nodes.len());
-            self.nodes.entry(node2).or_insert(GraphNode::new(node2));
+    /// Adds an undirected edge, and its nodes if they are new.  A self
+    /// loop is one step from the node to itself.
+    pub fn add_edge(&mut self, node1: NodeID, node2: NodeID) {
+        self.node_entry(node1).add_edge(node2);
+        if node1 != node2 {
+            self.node_entry(node2).add_edge(node1);
+        }
+    }
 
-            if let Some(node) = self.nodes.get_mut(&node1) {
-                node.add_edge(node2);
+    /// Counts duplicate edges and self loops and applies `policy` to them.
+    /// Merged and summed edges keep the position of their first occurrence.
+    pub fn apply_edge_policy(&mut self, policy: EdgePolicy) -> EdgeCounts {
+        if policy.duplicates == DuplicateEdges::Keep && policy.self_loops == SelfLoops::Keep {
+            return self.count_edges();
+        }
+        let mut counts = EdgeCounts::default();
+        let mut positions = HashMap::new();
+        for node in self.nodes.values_mut() {
+            let id = node.node_id;
+            let weights: Vec<f32> = match node.get_edge_weights() {
+                Some(w) => w.to_vec(),
+                None => vec![1.0; node.edge_list.len()],
+            };
+            let (mut edges, mut merged) = (Vec::new(), Vec::new());
+            positions.clear();
+            for (neighbor, weight) in node.edge_list.iter().zip(weights) {
+                if *neighbor == id {
+                    counts.self_loops += 1;
+                    if policy.self_loops == SelfLoops::Drop {
+                        continue;
+                    }
+                }
+                match positions.get(neighbor) {
+                    Some(pos) => {
+                        // The other end of an edge counts it too.
+                        if *neighbor >= id {
+                            counts.duplicates += 1;
+                        }
+                        if policy.duplicates == DuplicateEdges::Keep {
+                            edges.push(*neighbor);
+                            merged.push(weight);
+                        } else {
+                            merged[*pos] += weight;
+                        }
+                    }
+                    None => {
+                        positions.insert(*neighbor, edges.len());
+                        edges.push(*neighbor);
+                        merged.push(weight);
+                    }
+                }
             }
+            let weighted =
+                policy.duplicates == DuplicateEdges::Sum || !node.edge_weights.is_empty();
+            node.num_edges = edges.len() as i32;
+            node.edge_list = edges;
+            node.set_edge_weights(if weighted { merged } else { Vec::new() });
+        }
+        counts
+    }
 
-            if let Some(node) = self.nodes.get_mut(&node2) {
-                node.add_edge(node1);
+    /// Counts duplicate edges and self loops like `apply_edge_policy` with
+    /// nothing to apply, without changing or copying the graph.
+    fn count_edges(&self) -> EdgeCounts {
+        let mut counts = EdgeCounts::default();
+        let mut sorted = Vec::new();
+        for node in self.nodes.values() {
+            let id = node.node_id;
+            sorted.clear();
+            sorted.extend_from_slice(&node.edge_list);
+            sorted.sort_unstable();
+            for (i, neighbor) in sorted.iter().enumerate() {
+                if *neighbor == id {
+                    counts.self_loops += 1;
+                }
+                // The other end of an edge counts it too.
+                if i > 0 && sorted[i - 1] == *neighbor && *neighbor >= id {
+                    counts.duplicates += 1;
+                }
             }
         }
+        counts
+    }
+
+    /// Whether the edges carry weights.
+    pub fn is_weighted(&self) -> bool {
+        self.nodes.values().any(|n| n.get_edge_weights().is_some())
+    }
+
+    /// Builds a graph from nodes in index order with their neighbor lists
+    /// and edge weights, empty if unweighted, e.g. read back from a cache.
+    /// The neighbor lists must be symmetric.
+    pub fn from_adjacency<I>(nodes: I) -> Graph
+    where
+        I: IntoIterator<Item = (NodeID, Vec<NodeID>, Vec<f32>)>,
+    {
+        let mut graph = Graph::new();
+        for (node_id, edge_list, edge_weights) in nodes {
+            let mut node = GraphNode::new(node_id);
+            node.num_edges = edge_list.len() as i32;
+            node.edge_list = edge_list;
+            node.set_edge_weights(edge_weights);
+            graph.node_to_idx_map.insert(node_id, graph.nodes.len());
+            graph.nodes.insert(node_id, node);
+        }
+        graph
+    }
+
+    /// Adds the edges from `node_id` to each of `neighbors`, and the node
+    /// if it is new, without adding the reverse edges.  A new node takes
+    /// the list as is.
+    fn add_neighbors(&mut self, node_id: NodeID, neighbors: Vec<NodeID>) {
+        let node = self.node_entry(node_id);
+        if node.edge_list.is_empty() && node.edge_weights.is_empty() {
+            node.num_edges = neighbors.len() as i32;
+            node.edge_list = neighbors;
+        } else {
+            neighbors.into_iter().for_each(|n| node.add_edge(n));
+        }
+    }
+
+    /// Adds a node without edges if it is not already in the graph.
+    pub fn add_node(&mut self, node_id: NodeID) {
+        self.node_entry(node_id);
+    }
+
+    /// The node `node_id`, added without edges if it is not in the graph.
+    fn node_entry(&mut self, node_id: NodeID) -> &mut GraphNode {
+        self.node_to_idx_map
+            .entry(node_id)
+            .or_insert(self.nodes.len());
+        self.nodes
+            .entry(node_id)
+            .or_insert_with(|| GraphNode::new(node_id))
+    }
+
+    /// Removes one undirected edge between `node1` and `node2`, the nodes
+    /// stay in the graph.
+    pub fn remove_edge(&mut self, node1: NodeID, node2: NodeID) {
+        if let Some(node) = self.nodes.get_mut(&node1) {
+            node.remove_edge(node2);
+        }
+        if let Some(node) = self.nodes.get_mut(&node2) {
+            node.remove_edge(node1);
+        }
     }
 
     pub fn random_walk(&self, starting_node: &NodeID, num_steps: usize) -> Vec<NodeID> {
         let mut curr_node = self
             .get_node(starting_node)
             .expect("Start node does not exist.");
-        if curr_node.edge_list.len() == 0 {
+        if curr_node.edge_list.is_empty() {
             return vec![];
         }
         let mut path = Vec::with_capacity(num_steps);
         for _ in 0..num_steps {
-            let next_node = match curr_node.random_step() {
-                Some(n) => n,
-                None => panic!("The graph is malformed"),
-            };
-            curr_node = self.get_node(next_node).expect(
-                &format!(
-                    "Node: {} points to non existant node {}",
-                    curr_node.node_id, next_node
-                )[..],
-            );
-            path.push(*next_node);
+            curr_node = self.step(curr_node, None);
+            path.push(curr_node.node_id);
         }
 
         path
     }
 
+    /// A walk like `random_walk` that never steps back to the node it came
+    /// from, unless that is the only neighbor it can step to.
+    pub fn non_backtracking_walk(&self, starting_node: &NodeID, num_steps: usize) -> Vec<NodeID> {
+        self.random_walk_with_restart(starting_node, num_steps, 0.0, true)
+    }
+
+    /// A walk of `num_steps` steps from `starting_node` that returns to it
+    /// with probability `alpha` instead of every step, a return visits the
+    /// start.  The visits follow the personalized PageRank of the start.
+    /// With `non_backtracking` no step goes back to the previous node, see
+    /// `non_backtracking_walk`.
+    pub fn random_walk_with_restart(
+        &self,
+        starting_node: &NodeID,
+        num_steps: usize,
+        alpha: f64,
+        non_backtracking: bool,
+    ) -> Vec<NodeID> {
+        let start = self
+            .get_node(starting_node)
+            .expect("Start node does not exist.");
+        if start.edge_list.is_empty() {
+            return vec![];
+        }
+        let mut rng = rand::thread_rng();
+        let (mut curr_node, mut previous) = (start, None);
+        let mut path = Vec::with_capacity(num_steps);
+        for _ in 0..num_steps {
+            if alpha > 0.0 && rng.gen::<f64>() < alpha {
+                curr_node = start;
+                previous = None;
+            } else {
+                let next_node = self.step(curr_node, previous);
+                if non_backtracking {
+                    previous = Some(curr_node.node_id);
+                }
+                curr_node = next_node;
+            }
+            path.push(curr_node.node_id);
+        }
+        path
+    }
+
+    /// The stopping nodes of `num_walks` walks from `starting_node` that
+    /// stop after every step with probability `alpha`, the pairs of the
+    /// start with them are samples of its personalized PageRank, as in
+    /// APP.  Walks that stop at the start are dropped.
+    pub fn stopping_nodes(
+        &self,
+        starting_node: &NodeID,
+        num_walks: usize,
+        alpha: f64,
+        non_backtracking: bool,
+    ) -> Vec<NodeID> {
+        let start = self
+            .get_node(starting_node)
+            .expect("Start node does not exist.");
+        if start.edge_list.is_empty() {
+            return vec![];
+        }
+        let mut rng = rand::thread_rng();
+        let mut stops = Vec::with_capacity(num_walks);
+        for _ in 0..num_walks {
+            let (mut curr_node, mut previous) = (self.step(start, None), start.node_id);
+            while rng.gen::<f64>() >= alpha {
+                let next_node = self.step(curr_node, Some(previous).filter(|_| non_backtracking));
+                previous = curr_node.node_id;
+                curr_node = next_node;
+            }
+            if curr_node.node_id != start.node_id {
+                stops.push(curr_node.node_id);
+            }
+        }
+        stops
+    }
+
+    /// A random neighbor of `node`, which must have one, other than
+    /// `previous` if possible.
+    fn step(&self, node: &GraphNode, previous: Option<NodeID>) -> &GraphNode {
+        let next_node = match previous {
+            Some(previous) => node.non_backtracking_step(previous),
+            None => node.random_step(),
+        };
+        let next_node = match next_node {
+            Some(n) => n,
+            None => panic!("The graph is malformed"),
+        };
+        self.get_node(next_node).unwrap_or_else(|| {
+            panic!(
+                "Node: {} points to non existant node {}",
+                node.node_id, next_node
+            )
+        })
+    }
+
+    /// Counts how often each node is visited in one walk from every node.
+    pub fn walk_frequencies(&self, num_steps: usize) -> HashMap<NodeID, u64> {
+        let mut frequencies = HashMap::with_capacity(self.num_nodes());
+        for node in self.get_node_iter() {
+            for visited in self.random_walk(&node.get_id(), num_steps) {
+                *frequencies.entry(visited).or_insert(0) += 1;
+            }
+        }
+        frequencies
+    }
+
     pub fn get_node(&self, node_id: &NodeID) -> Option<&GraphNode> {
         self.nodes.get(node_id)
     }
 
-    pub fn build_graph_from_file(&mut self, filename: &str) {
-        let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
+    pub fn build_graph_from_file(&mut self, filename: &str) -> Result<(), DeepwalkError> {
+        loader::load_edge_list(self, filename, &EdgeListFormat::default())?;
+        Ok(())
+    }
 
-        let mut edges = Vec::new();
-        for line in contents.lines() {
-            let mut edge = Vec::with_capacity(2);
-            for node in line.split_whitespace() {
-                edge.push(node.parse::<i32>().unwrap());
+    /// Reads `node_id count` pairs, one per line, e.g. visit counts from a
+    /// previously generated walk corpus.  The file may be compressed or `-`
+    /// for stdin.
+    pub fn read_node_frequencies(filename: &str) -> Result<HashMap<NodeID, u64>, DeepwalkError> {
+        let mut frequencies = HashMap::new();
+        for (i, line) in input::open(filename)?.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            if let (Some(node), Some(count)) = (tokens.next(), tokens.next()) {
+                frequencies.insert(
+                    parse_token::<NodeID>(node, i + 1)?,
+                    parse_token::<u64>(count, i + 1)?,
+                );
             }
-            edges.push(edge);
         }
-
-        self.build(edges);
+        Ok(frequencies)
     }
 
-    pub fn get_node_iter(&self) -> hash_map::Values<NodeID, GraphNode> {
+    pub fn get_node_iter(&self) -> hash_map::Values<'_, NodeID, GraphNode> {
         self.nodes.values()
     }
 
@@ -135,11 +514,252 @@ impl Graph {
         self.node_to_idx_map.get(node_id)
     }
 
+    /// Node ids ordered by their index.
+    pub fn node_ids_by_idx(&self) -> Vec<NodeID> {
+        let mut node_ids = vec![0; self.num_nodes()];
+        for (node_id, idx) in &self.node_to_idx_map {
+            node_ids[*idx] = *node_id;
+        }
+        node_ids
+    }
+
+    /// Maps a walk of node ids to node indices.
+    pub fn walk_to_idx(&self, walk: &[NodeID]) -> Vec<usize> {
+        walk.iter()
+            .map(|node_id| self.node_to_idx_map[node_id])
+            .collect()
+    }
+
+    /// The connected component of every node, by node index.  Components
+    /// are numbered by decreasing size, ties by their smallest node index,
+    /// so component 0 is the largest.
+    pub fn component_labels(&self) -> Vec<usize> {
+        let n = self.num_nodes();
+        let mut parent: Vec<usize> = (0..n).collect();
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for node in self.nodes.values() {
+            let a = self.node_to_idx_map[&node.node_id];
+            for neighbor in &node.edge_list {
+                let b = self.node_to_idx_map[neighbor];
+                let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+                // The smaller index becomes the root.
+                if ra < rb {
+                    parent[rb] = ra;
+                } else if rb < ra {
+                    parent[ra] = rb;
+                }
+            }
+        }
+        let roots: Vec<usize> = (0..n).map(|i| find(&mut parent, i)).collect();
+
+        let mut sizes = vec![0; n];
+        for root in &roots {
+            sizes[*root] += 1;
+        }
+        let mut order: Vec<usize> = (0..n).filter(|i| sizes[*i] > 0).collect();
+        order.sort_by_key(|root| (cmp::Reverse(sizes[*root]), *root));
+        let mut labels = vec![0; n];
+        for (label, root) in order.into_iter().enumerate() {
+            labels[root] = label;
+        }
+        roots.into_iter().map(|root| labels[root]).collect()
+    }
+
+    /// The number of nodes in each component of `component_labels`.
+    pub fn component_sizes(&self) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        for label in self.component_labels() {
+            if label >= sizes.len() {
+                sizes.resize(label + 1, 0);
+            }
+            sizes[label] += 1;
+        }
+        sizes
+    }
+
+    /// Keeps the components for which `keep(component, size)` is true, with
+    /// the numbering of `component_labels`, and removes the other nodes.
+    /// Remaining nodes keep their relative index order.  Returns the
+    /// number of removed nodes.
+    pub fn retain_components<F>(&mut self, keep: F) -> usize
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        let labels = self.component_labels();
+        let sizes = self.component_sizes();
+        let node_ids = self.node_ids_by_idx();
+        let before = self.num_nodes();
+        self.node_to_idx_map.clear();
+        for (node_id, label) in node_ids.into_iter().zip(labels) {
+            if keep(label, sizes[label]) {
+                self.node_to_idx_map
+                    .insert(node_id, self.node_to_idx_map.len());
+            } else {
+                self.nodes.remove(&node_id);
+            }
+        }
+        before - self.num_nodes()
+    }
+
+    /// Counts, degree distribution, components and clustering of the
+    /// graph, see [`GraphStats`](crate::stats::GraphStats).
+    pub fn stats(&self) -> GraphStats {
+        stats::compute(self)
+    }
+
+    /// Attaches node attributes, keyed by node id.  Returns the number of
+    /// nodes of the graph without attributes.
+    pub fn set_attributes(&mut self, attributes: NodeAttributes) -> usize {
+        self.attributes = attributes;
+        self.nodes
+            .keys()
+            .filter(|node| self.attributes.get_all(**node).is_none())
+            .count()
+    }
+
+    /// The node attributes, empty unless set.
+    pub fn attributes(&self) -> &NodeAttributes {
+        &self.attributes
+    }
+
     pub fn get_node_id_to_idx(&self) -> &HashMap<NodeID, usize> {
         &self.node_to_idx_map
     }
 }
 
+/// Collects edges and adds them to a graph in two passes, one giving the
+/// nodes their indices and counting their degrees and one filling neighbor
+/// lists of exactly that size.  Each edge costs a lookup of its nodes
+/// instead of insertions into the graph's maps, and the lists never regrow.
+#[derive(Debug, Default)]
+pub struct GraphBuilder {
+    edges: Vec<(NodeID, NodeID)>,
+    /// Nodes added on their own, after the given number of edges.
+    nodes: Vec<(usize, NodeID)>,
+}
+
+impl GraphBuilder {
+    pub fn new() -> GraphBuilder {
+        GraphBuilder::default()
+    }
+
+    /// Adds a node, without edges if it has none.
+    pub fn add_node(&mut self, node_id: NodeID) {
+        self.nodes.push((self.edges.len(), node_id));
+    }
+
+    /// Adds an undirected edge, like `Graph::add_edge`.
+    pub fn add_edge(&mut self, node1: NodeID, node2: NodeID) {
+        self.edges.push((node1, node2));
+    }
+
+    /// Adds the nodes and edges to `graph`.  Nodes new to it get indices in
+    /// the order they were first added here, and every neighbor list keeps
+    /// the order of the edges, as if they had been added to `graph` one by
+    /// one.
+    pub fn build_into(self, graph: &mut Graph) {
+        let ids = self
+            .edges
+            .iter()
+            .flat_map(|(a, b)| [*a, *b])
+            .chain(self.nodes.iter().map(|(_, id)| *id));
+        let (min, max) = ids.fold((NodeID::MAX, NodeID::MIN), |(min, max), id| {
+            (min.min(id), max.max(id))
+        });
+        let mut index = NodeIndex::new(min, max, 2 * self.edges.len() + self.nodes.len());
+        let mut degrees = Vec::new();
+        let mut nodes = self.nodes.iter().peekable();
+        for (i, (a, b)) in self.edges.iter().enumerate() {
+            while let Some((_, id)) = nodes.next_if(|(before, _)| *before <= i) {
+                index.insert(*id, 0, &mut degrees);
+            }
+            index.insert(*a, 1, &mut degrees);
+            if a != b {
+                index.insert(*b, 1, &mut degrees);
+            }
+        }
+        for (_, id) in nodes {
+            index.insert(*id, 0, &mut degrees);
+        }
+
+        let mut lists: Vec<Vec<NodeID>> = degrees.into_iter().map(Vec::with_capacity).collect();
+        for (a, b) in self.edges {
+            lists[index.get(a)].push(b);
+            if a != b {
+                lists[index.get(b)].push(a);
+            }
+        }
+        graph.nodes.reserve(index.node_ids.len());
+        graph.node_to_idx_map.reserve(index.node_ids.len());
+        for (node_id, neighbors) in index.node_ids.into_iter().zip(lists) {
+            graph.add_neighbors(node_id, neighbors);
+        }
+    }
+}
+
+/// Dense indices of node ids in the order they are inserted.  Ids spanning
+/// a range no larger than the number of `GraphBuilder` entries, as
+/// numbered nodes usually do, are looked up in a table, which is several
+/// times faster than a map and no larger than the edges.
+struct NodeIndex {
+    node_ids: Vec<NodeID>,
+    min: NodeID,
+    table: Vec<u32>,
+    map: HashMap<NodeID, u32>,
+}
+
+impl NodeIndex {
+    const MISSING: u32 = u32::MAX;
+
+    fn new(min: NodeID, max: NodeID, entries: usize) -> NodeIndex {
+        let span = (max as i64 - min as i64 + 1).max(0) as usize;
+        NodeIndex {
+            node_ids: Vec::new(),
+            min,
+            table: if span <= entries {
+                vec![NodeIndex::MISSING; span]
+            } else {
+                Vec::new()
+            },
+            map: HashMap::new(),
+        }
+    }
+
+    /// Indexes `node_id` if it is new and adds `degree` to its entry of
+    /// `degrees`.
+    fn insert(&mut self, node_id: NodeID, degree: usize, degrees: &mut Vec<usize>) {
+        let next = self.node_ids.len() as u32;
+        let idx = if self.table.is_empty() {
+            *self.map.entry(node_id).or_insert(next)
+        } else {
+            let slot = &mut self.table[(node_id as i64 - self.min as i64) as usize];
+            if *slot == NodeIndex::MISSING {
+                *slot = next;
+            }
+            *slot
+        };
+        if idx == next {
+            self.node_ids.push(node_id);
+            degrees.push(0);
+        }
+        degrees[idx as usize] += degree;
+    }
+
+    fn get(&self, node_id: NodeID) -> usize {
+        if self.table.is_empty() {
+            self.map[&node_id] as usize
+        } else {
+            self.table[(node_id as i64 - self.min as i64) as usize] as usize
+        }
+    }
+}
+
 #[cfg(test)]
 mod graph_tests {
     use super::*;
@@ -153,6 +773,8 @@ mod graph_tests {
             node_id: 333,
             edge_list: vec![111, 222],
             num_edges: 2,
+            edge_weights: vec![],
+            cumulative_weights: vec![],
         };
         assert_eq!(g.get_node(&333).unwrap(), &mock_333);
         assert_eq!(g.get_node_idx(&111).unwrap(), &0);
@@ -166,4 +788,244 @@ mod graph_tests {
         let random_walk = g.random_walk(&111, 5);
         assert_eq!(random_walk.len(), 5);
     }
+
+    #[test]
+    fn test_restart_walks() {
+        // A path 1 - 2 - ... - 10.
+        let mut g = Graph::new();
+        g.build((1..10).map(|i| vec![i, i + 1]).collect());
+
+        let walk = g.random_walk_with_restart(&1, 1000, 0.5, false);
+        assert_eq!(walk.len(), 1000);
+        let mut prev = 1;
+        for node in &walk {
+            assert!(*node == 1 || (node - prev).abs() == 1);
+            prev = *node;
+        }
+        // Each node is half as likely as the previous one to be reached.
+        let count = |n| walk.iter().filter(|m| **m == n).count();
+        assert!(count(1) > count(3) && count(3) > count(6));
+        assert_eq!(g.random_walk_with_restart(&5, 4, 1.0, false), vec![5; 4]);
+
+        assert_eq!(g.stopping_nodes(&1, 5, 1.0, false), vec![2; 5]);
+        let stops = g.stopping_nodes(&5, 1000, 0.2, false);
+        assert!(stops.len() <= 1000 && stops.iter().all(|n| *n != 5));
+
+        g.add_node(11);
+        assert!(g.random_walk_with_restart(&11, 3, 0.5, false).is_empty());
+        assert!(g.stopping_nodes(&11, 3, 0.5, false).is_empty());
+    }
+
+    #[test]
+    fn test_non_backtracking_walks() {
+        // A cycle of 6 nodes is walked around in one direction.
+        let mut g = Graph::new();
+        g.build((0..6).map(|i| vec![i, (i + 1) % 6]).collect());
+        let walk = g.non_backtracking_walk(&0, 50);
+        assert_eq!(walk.len(), 50);
+        for i in 2..walk.len() {
+            assert_ne!(walk[i], walk[i - 2], "{:?}", walk);
+        }
+        // Except for the first step after a restart.
+        let walk = g.random_walk_with_restart(&0, 50, 0.2, true);
+        for i in 2..walk.len() {
+            let restarted = walk[i] == 0 || walk[i - 1] == 0;
+            assert!(restarted || walk[i] != walk[i - 2], "{:?}", walk);
+        }
+
+        // The end of a path can only go back.
+        let mut g = Graph::new();
+        g.build(vec![vec![1, 2], vec![2, 3]]);
+        assert_eq!(g.non_backtracking_walk(&1, 4), vec![2, 3, 2, 1]);
+        assert_eq!(g.stopping_nodes(&1, 3, 1.0, true), vec![2; 3]);
+
+        // Heavy and parallel edges to the previous node are avoided too.
+        let mut node = GraphNode::new(1);
+        for target in [2, 2, 3].iter() {
+            node.add_edge(*target);
+        }
+        for _ in 0..20 {
+            assert_eq!(node.non_backtracking_step(2), Some(&3));
+        }
+        node.set_edge_weights(vec![100.0, 100.0, 1.0]);
+        for _ in 0..20 {
+            assert_eq!(node.non_backtracking_step(2), Some(&3));
+        }
+        node.set_edge_weights(vec![1.0, 1.0, 0.0]);
+        assert_eq!(node.non_backtracking_step(2), Some(&2));
+    }
+
+    #[test]
+    fn test_degenerate_graphs() {
+        let mut g = Graph::new();
+        g.build(vec![vec![111, 111]]);
+        assert_eq!(g.num_nodes(), 1);
+        assert_eq!(g.random_walk(&111, 3), vec![111, 111, 111]);
+
+        let mut g = Graph::new();
+        g.add_node(111);
+        g.add_node(222);
+        g.add_node(111);
+        assert_eq!(g.num_nodes(), 2);
+        assert!(g.random_walk(&222, 3).is_empty());
+        assert_eq!(g.walk_frequencies(3).len(), 0);
+
+        let mut g = Graph::new();
+        g.build(vec![vec![111, 222], vec![111, 222]]);
+        g.remove_edge(222, 111);
+        assert_eq!(g.get_node(&111).unwrap().get_neighbors(), &[222]);
+        assert_eq!(g.get_node(&222).unwrap().get_weight(), 1);
+        g.remove_edge(111, 222);
+        assert!(g.random_walk(&111, 3).is_empty());
+    }
+
+    #[test]
+    fn test_edge_policy() {
+        let edges = || {
+            vec![
+                vec![1, 2],
+                vec![2, 1],
+                vec![1, 2],
+                vec![2, 3],
+                vec![3, 3],
+                vec![3, 3],
+            ]
+        };
+        let policy = |duplicates, self_loops| EdgePolicy {
+            duplicates,
+            self_loops,
+        };
+
+        let mut g = Graph::new();
+        g.build(edges());
+        let counts = g.apply_edge_policy(policy(DuplicateEdges::Keep, SelfLoops::Keep));
+        assert_eq!(
+            counts,
+            EdgeCounts {
+                duplicates: 3,
+                self_loops: 2
+            }
+        );
+        assert_eq!(g.get_node(&1).unwrap().get_neighbors(), &[2, 2, 2]);
+        assert_eq!(g.get_node(&3).unwrap().get_neighbors(), &[2, 3, 3]);
+        assert!(!g.is_weighted());
+
+        let mut g = Graph::new();
+        g.build(edges());
+        g.apply_edge_policy(policy(DuplicateEdges::Merge, SelfLoops::Drop));
+        assert_eq!(g.get_node(&2).unwrap().get_neighbors(), &[1, 3]);
+        assert_eq!(g.get_node(&3).unwrap().get_neighbors(), &[2]);
+        assert!(!g.is_weighted());
+
+        let mut g = Graph::new();
+        g.build(edges());
+        // Counting alone agrees with the pass that rebuilds the lists.
+        assert_eq!(
+            g.apply_edge_policy(policy(DuplicateEdges::Sum, SelfLoops::Keep)),
+            counts
+        );
+        let node = g.get_node(&2).unwrap();
+        assert_eq!(node.get_neighbors(), &[1, 3]);
+        assert_eq!(node.get_edge_weights(), Some(&[3.0, 1.0][..]));
+        assert_eq!(node.get_weight(), 2);
+        assert_eq!(node.get_weighted_degree(), 4.0);
+        assert_eq!(
+            g.get_node(&3).unwrap().get_edge_weights(),
+            Some(&[1.0, 2.0][..])
+        );
+
+        let mut steps = HashMap::new();
+        for _ in 0..4000 {
+            *steps.entry(*node.random_step().unwrap()).or_insert(0) += 1;
+        }
+        assert!(steps[&1] > 2700 && steps[&3] > 800);
+
+        // Removing an edge keeps the weights aligned.
+        g.remove_edge(2, 1);
+        let node = g.get_node(&2).unwrap();
+        assert_eq!(node.get_edge_weights(), Some(&[1.0][..]));
+        assert_eq!(node.random_step(), Some(&3));
+    }
+
+    #[test]
+    fn test_graph_builder() {
+        // Numbered ids use the table, spread out ids the map.
+        for scale in [1, 1 << 20, -7].iter() {
+            let edges = [(1, 2), (3, 3), (2, 3), (1, 2), (5, 1)];
+            let mut expected = Graph::new();
+            let mut builder = GraphBuilder::new();
+            builder.add_node(4 * scale);
+            expected.add_node(4 * scale);
+            for (i, (a, b)) in edges.iter().enumerate() {
+                builder.add_edge(a * scale, b * scale);
+                expected.add_edge(a * scale, b * scale);
+                if i == 2 {
+                    builder.add_node(6 * scale);
+                    expected.add_node(6 * scale);
+                }
+            }
+            builder.add_node(7 * scale);
+            expected.add_node(7 * scale);
+
+            let mut g = Graph::new();
+            builder.build_into(&mut g);
+            assert_eq!(g.node_ids_by_idx(), expected.node_ids_by_idx());
+            for node in expected.get_node_iter() {
+                assert_eq!(g.get_node(&node.get_id()), Some(node));
+            }
+        }
+
+        // Into a graph with nodes, existing lists are extended.
+        let mut g = Graph::new();
+        g.add_edge(1, 2);
+        let mut builder = GraphBuilder::new();
+        builder.add_edge(3, 1);
+        builder.build_into(&mut g);
+        assert_eq!(g.node_ids_by_idx(), vec![1, 2, 3]);
+        assert_eq!(g.get_node(&1).unwrap().get_neighbors(), &[2, 3]);
+        assert_eq!(g.get_node(&3).unwrap().get_neighbors(), &[1]);
+    }
+
+    #[test]
+    fn test_weighted_edits() {
+        let mut node = GraphNode::new(1);
+        for target in [2, 3, 4].iter() {
+            node.add_edge(*target);
+        }
+        node.set_edge_weights(vec![1.0, 2.0, 3.0]);
+        node.add_edge(5);
+        assert_eq!(node.cumulative_weights, vec![1.0, 3.0, 6.0, 7.0]);
+        assert!(node.remove_edge(2));
+        assert_eq!(node.get_neighbors(), &[5, 3, 4]);
+        assert_eq!(node.cumulative_weights, vec![1.0, 3.0, 6.0]);
+        assert!(node.remove_edge(4));
+        assert_eq!(node.cumulative_weights, vec![1.0, 3.0]);
+        assert_eq!(node.get_weighted_degree(), 3.0);
+    }
+
+    #[test]
+    fn test_walk_frequencies() {
+        let edge_list = vec![vec![111, 222], vec![111, 333], vec![222, 333]];
+        let mut g = Graph::new();
+        g.build(edge_list);
+        let frequencies = g.walk_frequencies(5);
+        assert_eq!(frequencies.values().sum::<u64>(), 15);
+    }
+
+    #[test]
+    fn test_components() {
+        // A pair, a triangle and an isolated node.
+        let mut g = Graph::new();
+        g.build(vec![vec![1, 2], vec![3, 4], vec![4, 5], vec![5, 3]]);
+        g.add_node(6);
+        assert_eq!(g.component_labels(), vec![1, 1, 0, 0, 0, 2]);
+        assert_eq!(g.component_sizes(), vec![3, 2, 1]);
+
+        assert_eq!(g.retain_components(|_, size| size >= 2), 1);
+        assert_eq!(g.node_ids_by_idx(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(g.retain_components(|component, _| component == 0), 2);
+        assert_eq!(g.node_ids_by_idx(), vec![3, 4, 5]);
+        assert_eq!(g.get_node_idx(&3), Some(&0));
+        assert_eq!(g.component_labels(), vec![0, 0, 0]);
+    }
 }
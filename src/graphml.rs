@@ -0,0 +1,193 @@
+//! The subset of GraphML and GEXF that describes structure: `node` elements
+//! with an `id` and `edge` elements with a `source` and `target`.  Both
+//! formats use the same element names, so one streaming reader handles
+//! them.  Attributes, data keys and edge directions are ignored.
+//!
+//! Node ids must be integers or end in one, like the common `n12`, and two
+//! ids may not map to the same number.
+
+use crate::error::DeepwalkError;
+use crate::graph::{Graph, NodeID};
+use crate::input;
+use crate::loader::LoadReport;
+use crate::logging;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+/// Loads the nodes and edges of the GraphML or GEXF file `filename`.
+pub fn load(graph: &mut Graph, filename: &str) -> Result<LoadReport, DeepwalkError> {
+    read(graph, input::open(filename)?)
+}
+
+/// Reads the nodes and edges of a GraphML or GEXF document from `input`.
+pub fn read<R: BufRead>(graph: &mut Graph, input: R) -> Result<LoadReport, DeepwalkError> {
+    let mut reader = Reader::from_reader(input);
+    let mut ids = NodeIds::default();
+    let mut report = LoadReport::default();
+    let bar = logging::spinner("loading", "elements");
+    let mut buf = Vec::new();
+    loop {
+        let event = reader.read_event_into(&mut buf).map_err(|e| {
+            DeepwalkError::Format(format!("{} at byte {}", e, reader.error_position()))
+        })?;
+        let position = reader.buffer_position();
+        let element = match &event {
+            Event::Start(e) | Event::Empty(e) => e,
+            Event::Eof => break,
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+        match element.local_name().as_ref() {
+            b"node" => {
+                let id = ids.get(&attribute(element, "id", position)?, position)?;
+                graph.add_node(id);
+                bar.inc(1);
+            }
+            b"edge" => {
+                let source = ids.get(&attribute(element, "source", position)?, position)?;
+                let target = ids.get(&attribute(element, "target", position)?, position)?;
+                graph.add_edge(source, target);
+                report.edges += 1;
+                bar.inc(1);
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    bar.finish_and_clear();
+    Ok(report)
+}
+
+fn attribute(element: &BytesStart<'_>, name: &str, position: u64) -> Result<String, DeepwalkError> {
+    let format_error =
+        |message: String| DeepwalkError::Format(format!("{} at byte {}", message, position));
+    let attribute = element
+        .try_get_attribute(name)
+        .map_err(|e| format_error(e.to_string()))?
+        .ok_or_else(|| {
+            format_error(format!(
+                "{} without a {} attribute",
+                String::from_utf8_lossy(element.local_name().as_ref()),
+                name
+            ))
+        })?;
+    let value = attribute
+        .unescape_value()
+        .map_err(|e| format_error(e.to_string()))?;
+    Ok(value.into_owned())
+}
+
+/// Maps string ids to node ids and remembers them to catch collisions.
+#[derive(Debug, Default)]
+struct NodeIds {
+    seen: HashMap<NodeID, String>,
+}
+
+impl NodeIds {
+    fn get(&mut self, id: &str, position: u64) -> Result<NodeID, DeepwalkError> {
+        let node = numeric_id(id).ok_or_else(|| {
+            DeepwalkError::Format(format!(
+                "node id {:?} at byte {} does not end in a number",
+                id, position
+            ))
+        })?;
+        match self.seen.get(&node) {
+            Some(other) if other != id => Err(DeepwalkError::Format(format!(
+                "node ids {:?} and {:?} both map to {}",
+                other, id, node
+            ))),
+            Some(_) => Ok(node),
+            None => {
+                self.seen.insert(node, id.to_string());
+                Ok(node)
+            }
+        }
+    }
+}
+
+/// `id` as a number, or its trailing digits.
+fn numeric_id(id: &str) -> Option<NodeID> {
+    if let Ok(node) = id.parse() {
+        return Some(node);
+    }
+    let digits = id.len() - id.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    id[id.len() - digits..].parse().ok()
+}
+
+#[cfg(test)]
+mod graphml_tests {
+    use super::*;
+
+    fn read_str(contents: &str) -> Result<Graph, DeepwalkError> {
+        let mut g = Graph::new();
+        read(&mut g, contents.as_bytes()).map(|_| g)
+    }
+
+    #[test]
+    fn test_graphml() {
+        let contents = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="d0" for="node" attr.name="color" attr.type="string"/>
+  <graph id="G" edgedefault="undirected">
+    <node id="n0"><data key="d0">green</data></node>
+    <node id="n1"/>
+    <node id="n2"/>
+    <node id="n3"/>
+    <edge source="n0" target="n2"/>
+    <edge id="e1" source="n1" target="n2"></edge>
+  </graph>
+</graphml>"#;
+        let g = read_str(contents).unwrap();
+        assert_eq!(g.node_ids_by_idx(), vec![0, 1, 2, 3]);
+        assert_eq!(g.get_node(&2).unwrap().get_neighbors(), &[0, 1]);
+        assert!(g.get_node(&3).unwrap().get_neighbors().is_empty());
+    }
+
+    #[test]
+    fn test_gexf() {
+        let contents = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gexf xmlns="http://gexf.net/1.3" version="1.3">
+  <graph mode="static" defaultedgetype="undirected">
+    <nodes>
+      <node id="10" label="a"/>
+      <node id="20" label="b"/>
+    </nodes>
+    <edges>
+      <edge id="0" source="10" target="20" weight="2.0"/>
+    </edges>
+  </graph>
+</gexf>"#;
+        let g = read_str(contents).unwrap();
+        assert_eq!(g.node_ids_by_idx(), vec![10, 20]);
+        assert_eq!(g.get_node(&10).unwrap().get_neighbors(), &[20]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            r#"<graph><node id="a"/></graph>"#,
+            r#"<graph><node id="a1"/><node id="b1"/></graph>"#,
+            r#"<graph><edge source="1"/></graph>"#,
+            r#"<graph><node id="1"></graph>"#,
+        ];
+        for contents in cases.iter() {
+            assert!(
+                matches!(read_str(contents), Err(DeepwalkError::Format(_))),
+                "{}",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn test_numeric_id() {
+        assert_eq!(numeric_id("12"), Some(12));
+        assert_eq!(numeric_id("-3"), Some(-3));
+        assert_eq!(numeric_id("n12"), Some(12));
+        assert_eq!(numeric_id("node"), None);
+    }
+}
@@ -1,29 +1,82 @@
-use crate::graph::GraphNode;
-use std::cmp::Ordering;
+use crate::graph::Graph;
+use std::cmp::{self, Ordering};
 use std::collections::{HashMap, VecDeque};
 
 #[derive(Debug)]
 pub struct HuffmanTree {
     tree_vec: Vec<TreeNode>,
     leaf_id_idx_map: HashMap<i32, usize>,
+    // Flattened per leaf paths, word2vec's `point` and `code`.  The path of
+    // leaf `i` is `points[offsets[i]..offsets[i + 1]]`.
+    points: Vec<usize>,
+    codes: Vec<f64>,
+    offsets: Vec<usize>,
 }
 
 impl HuffmanTree {
-    pub fn new<'a, I>(graph: I) -> HuffmanTree
+    /// Builds the tree weighted by the rounded weighted degrees of the nodes
+    /// of `graph`, with leaf `i` for the node with index `i`.  See
+    /// `from_frequencies` for `max_code_length`.
+    pub fn new(graph: &Graph, max_code_length: usize) -> HuffmanTree {
+        HuffmanTree::from_frequencies(
+            graph.node_ids_by_idx().into_iter().map(|id| {
+                let weight = graph.get_node(&id).unwrap().get_weighted_degree();
+                (id, weight.round() as i64)
+            }),
+            max_code_length,
+        )
+    }
+
+    /// Builds the tree from `(node_id, frequency)` pairs, e.g. visit counts
+    /// observed in a walk corpus.  The `i`th pair becomes leaf `i` in
+    /// `get_points_and_codes`.
+    ///
+    /// If `max_code_length` is non zero and the optimal tree is deeper than
+    /// that, small frequencies are raised to a common floor until the tree
+    /// fits.  Pass 0 for no limit.
+    ///
+    /// # Panics
+    ///
+    /// If `max_code_length` is non zero and shorter than
+    /// [`min_code_length`] of the number of frequencies.
+    pub fn from_frequencies<I>(frequencies: I, max_code_length: usize) -> HuffmanTree
     where
-        I: ExactSizeIterator<Item = &'a GraphNode>,
+        I: IntoIterator<Item = (i32, i64)>,
     {
-        let mut init_tree_nodes = Vec::with_capacity(graph.len());
-
-        for gn in graph {
-            let mut new_node = TreeNode::new(gn.get_weight());
-            new_node.set_leaf_id(gn.get_id());
-            init_tree_nodes.push(new_node);
+        let frequencies: Vec<(i32, i64)> = frequencies.into_iter().collect();
+        let ht = HuffmanTree::build(&frequencies, 0);
+        if max_code_length == 0 || ht.max_code_length() <= max_code_length {
+            return ht;
         }
-        if init_tree_nodes.len() <= 1 {
-            panic!("We only have one leaf");
+
+        let min_length = min_code_length(frequencies.len());
+        assert!(
+            max_code_length >= min_length,
+            "A max_code_length of {} is too short for {} nodes, at least {} is needed",
+            max_code_length,
+            frequencies.len(),
+            min_length
+        );
+
+        let max_weight = frequencies.iter().map(|(_, w)| *w).max().unwrap();
+        let mut floor = 1;
+        loop {
+            let ht = HuffmanTree::build(&frequencies, floor);
+            if ht.max_code_length() <= max_code_length || floor >= max_weight {
+                return ht;
+            }
+            floor *= 2;
         }
+    }
 
+    fn build(frequencies: &[(i32, i64)], floor: i64) -> HuffmanTree {
+        let mut init_tree_nodes = Vec::with_capacity(frequencies.len());
+
+        for (node_id, weight) in frequencies {
+            let mut new_node = TreeNode::new(cmp::max(*weight, floor));
+            new_node.set_leaf_id(*node_id);
+            init_tree_nodes.push(new_node);
+        }
         init_tree_nodes.sort_by(|a, b| b.cmp(a));
         let mut first_queue = VecDeque::with_capacity(init_tree_nodes.len());
         while let Some(node) = init_tree_nodes.pop() {
@@ -35,9 +88,17 @@ impl HuffmanTree {
         let mut ht = HuffmanTree {
             tree_vec: Vec::with_capacity((2 * first_queue.len()) + 1),
             leaf_id_idx_map: HashMap::new(),
+            points: Vec::new(),
+            codes: Vec::new(),
+            offsets: Vec::with_capacity(frequencies.len() + 1),
         };
 
-        while (first_queue.len() > 0) || (second_queue.len() > 1) {
+        // With a single leaf the leaf is the root and its code is empty.
+        if first_queue.len() == 1 {
+            second_queue.push_back(first_queue.pop_front().unwrap());
+        }
+
+        while !first_queue.is_empty() || (second_queue.len() > 1) {
             let mut right_child = get_min_from_queues(&mut first_queue, &mut second_queue);
             let mut left_child = get_min_from_queues(&mut first_queue, &mut second_queue);
             let new_weight = left_child.weight + right_child.weight;
@@ -66,12 +127,40 @@ impl HuffmanTree {
             tree_index += 1;
         }
 
-        let root_node = second_queue.pop_front().unwrap();
-        ht.tree_vec_push(root_node);
-        ht.update_parents();
+        if let Some(root_node) = second_queue.pop_front() {
+            if let Some(node_id) = root_node.leaf_id {
+                ht.leaf_id_idx_map.insert(node_id, ht.tree_vec.len());
+            }
+            ht.tree_vec_push(root_node);
+            ht.update_parents();
+        }
+        ht.precompute_paths(frequencies);
         ht
     }
 
+    fn precompute_paths(&mut self, frequencies: &[(i32, i64)]) {
+        self.offsets.push(0);
+        for (node_id, _) in frequencies {
+            let points = self.get_tree_indexes(node_id);
+            let codes = self.get_encoding(node_id);
+            self.points.extend(points);
+            self.codes.extend(codes);
+            self.offsets.push(self.points.len());
+        }
+    }
+
+    /// The inner node indices and turns on the path from leaf `leaf` to the
+    /// root, without allocating.
+    pub fn get_points_and_codes(&self, leaf: usize) -> (&[usize], &[f64]) {
+        let (start, end) = (self.offsets[leaf], self.offsets[leaf + 1]);
+        (&self.points[start..end], &self.codes[start..end])
+    }
+
+    /// The number of inner nodes, i.e. the number of output vectors needed.
+    pub fn num_inner_nodes(&self) -> usize {
+        self.tree_vec.len() - self.leaf_id_idx_map.len()
+    }
+
     fn tree_vec_push(&mut self, tree_node: TreeNode) {
         self.tree_vec.push(tree_node);
     }
@@ -79,21 +168,28 @@ impl HuffmanTree {
     fn update_parents(&mut self) {
         let mut stack = Vec::new();
         let n = self.tree_vec.len();
-        stack.push((self.tree_vec[n - 1].left.unwrap(), n - 1));
-        stack.push((self.tree_vec[n - 1].right.unwrap(), n - 1));
+        if let (Some(l), Some(r)) = (self.tree_vec[n - 1].left, self.tree_vec[n - 1].right) {
+            stack.push((l, n - 1));
+            stack.push((r, n - 1));
+        }
         while let Some((next, parent)) = stack.pop() {
             self.tree_vec[next].set_parent(parent);
-            match (self.tree_vec[next].left, self.tree_vec[next].right) {
-                (Some(l), Some(r)) => {
-                    stack.push((l, next));
-                    stack.push((r, next));
-                    ()
-                }
-                (_, _) => (),
+            if let (Some(l), Some(r)) = (self.tree_vec[next].left, self.tree_vec[next].right) {
+                stack.push((l, next));
+                stack.push((r, next));
             }
         }
     }
 
+    /// The length of the longest code, i.e. the depth of the tree.
+    pub fn max_code_length(&self) -> usize {
+        self.offsets
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .max()
+            .unwrap_or(0)
+    }
+
     pub fn get_indices_and_turns(&self, node_id: &i32) -> Vec<(usize, f64)> {
         let is_right_child = self.get_encoding(node_id);
         let node_indexes = self.get_tree_indexes(node_id);
@@ -130,20 +226,28 @@ impl HuffmanTree {
         indices
     }
 }
+/// The shortest code length limit a tree with `num_leaves` leaves can
+/// meet, `ceil(log2(num_leaves))`.
+pub fn min_code_length(num_leaves: usize) -> usize {
+    match num_leaves {
+        0 | 1 => 0,
+        n => (usize::BITS - (n - 1).leading_zeros()) as usize,
+    }
+}
+
 fn get_min_from_queues(
     first_queue: &mut VecDeque<TreeNode>,
     second_queue: &mut VecDeque<TreeNode>,
 ) -> TreeNode {
-    match (first_queue.get(0), second_queue.get(0)) {
+    match (first_queue.front(), second_queue.front()) {
         (None, Some(_)) => second_queue.pop_front().unwrap(),
         (Some(_), None) => first_queue.pop_front().unwrap(),
         (Some(a), Some(b)) => {
-            let o = if a.weight < b.weight {
+            if a.weight < b.weight {
                 first_queue.pop_front().unwrap()
             } else {
                 second_queue.pop_front().unwrap()
-            };
-            o
+            }
         }
         (None, None) => panic!("One of the queues should have entries"),
     }
@@ -154,7 +258,7 @@ pub struct TreeNode {
     is_right_child: Option<bool>,
     left: Option<usize>,
     right: Option<usize>,
-    weight: i32,
+    weight: i64,
     leaf_id: Option<i32>,
     tree_index: Option<usize>,
 }
@@ -178,13 +282,13 @@ impl PartialEq for TreeNode {
 }
 
 impl TreeNode {
-    pub fn new(weight: i32) -> TreeNode {
+    pub fn new(weight: i64) -> TreeNode {
         TreeNode {
             parent: None,
             is_right_child: None,
             left: None,
             right: None,
-            weight: weight,
+            weight,
             leaf_id: None,
             tree_index: None,
         }
@@ -214,29 +318,91 @@ mod tree_node_tests {
 
     #[test]
     fn test_new_tree() {
-        let mut g1 = GraphNode::new(111);
-        let mut g2 = GraphNode::new(222);
-        let g3 = GraphNode::new(333);
-        g1.add_edge(222);
-        g2.add_edge(333);
-        g1.add_edge(333);
-
-        let test_graph = vec![&g3, &g2, &g1];
-        let ht = HuffmanTree::new(test_graph.into_iter());
+        let mut g = Graph::new();
+        g.build(vec![vec![111, 222], vec![222, 333]]);
+        let ht = HuffmanTree::new(&g, 0);
         assert_eq!(ht.tree_vec.len(), 5);
+        // Leaves follow the node indices, whatever the order of the nodes
+        // in the graph's map.
+        for (idx, id) in g.node_ids_by_idx().iter().enumerate() {
+            let (points, _) = ht.get_points_and_codes(idx);
+            assert_eq!(points.len(), if *id == 222 { 1 } else { 2 }, "{}", id);
+        }
     }
 
     #[test]
     fn test_get_path_and_turns() {
-        let mut g1 = GraphNode::new(111);
-        let mut g2 = GraphNode::new(222);
-        let g3 = GraphNode::new(333);
-        g1.add_edge(222);
-        g2.add_edge(333);
-        g1.add_edge(333);
-
-        let test_graph = vec![&g3, &g2, &g1];
-        let ht = HuffmanTree::new(test_graph.into_iter());
+        let ht = HuffmanTree::from_frequencies(vec![(333, 0), (222, 1), (111, 2)], 0);
         assert_eq!(ht.get_indices_and_turns(&333), vec![(0, 1.0), (1, 1.0)]);
     }
+
+    #[test]
+    fn test_get_points_and_codes() {
+        let frequencies = vec![(111, 2), (222, 2), (333, 2)];
+        let ht = HuffmanTree::from_frequencies(frequencies, 0);
+        assert_eq!(ht.num_inner_nodes(), 2);
+        for (leaf, node_id) in [111, 222, 333].iter().enumerate() {
+            let (points, codes) = ht.get_points_and_codes(leaf);
+            let expected = ht.get_indices_and_turns(node_id);
+            assert_eq!(points.len(), expected.len());
+            for (i, (point, code)) in expected.iter().enumerate() {
+                assert_eq!(points[i], *point);
+                assert_eq!(codes[i], *code);
+            }
+        }
+    }
+
+    #[test]
+    fn test_degenerate_trees() {
+        let ht = HuffmanTree::from_frequencies(vec![], 0);
+        assert_eq!(ht.num_inner_nodes(), 0);
+        assert_eq!(ht.max_code_length(), 0);
+
+        let ht = HuffmanTree::from_frequencies(vec![(111, 3)], 0);
+        assert_eq!(ht.num_inner_nodes(), 0);
+        assert_eq!(ht.get_points_and_codes(0), (&[][..], &[][..]));
+        assert!(ht.get_indices_and_turns(&111).is_empty());
+    }
+
+    #[test]
+    fn test_from_frequencies() {
+        let frequencies = vec![(1, 1), (2, 2), (3, 4), (4, 8), (5, 16)];
+        let ht = HuffmanTree::from_frequencies(frequencies, 0);
+        assert_eq!(ht.tree_vec.len(), 9);
+        assert_eq!(ht.get_encoding(&5).len(), 1);
+        assert_eq!(ht.get_encoding(&1).len(), 4);
+        assert_eq!(ht.max_code_length(), 4);
+    }
+
+    #[test]
+    fn test_max_code_length() {
+        let frequencies: Vec<(i32, i64)> = (0..20).map(|i| (i, 1 << i)).collect();
+        assert_eq!(
+            HuffmanTree::from_frequencies(frequencies.clone(), 0).max_code_length(),
+            19
+        );
+
+        let ht = HuffmanTree::from_frequencies(frequencies.clone(), 6);
+        assert!(ht.max_code_length() <= 6);
+        assert_eq!(ht.leaf_id_idx_map.len(), 20);
+
+        let ht = HuffmanTree::from_frequencies(frequencies, 5);
+        assert_eq!(ht.max_code_length(), 5);
+    }
+
+    #[test]
+    fn test_min_code_length() {
+        let lengths: Vec<usize> = [0, 1, 2, 3, 4, 5, 34]
+            .iter()
+            .map(|n| min_code_length(*n))
+            .collect();
+        assert_eq!(lengths, vec![0, 0, 1, 2, 2, 3, 6]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_max_code_length_too_short() {
+        let frequencies: Vec<(i32, i64)> = (0..20).map(|i| (i, 1 << i)).collect();
+        HuffmanTree::from_frequencies(frequencies, 4);
+    }
 }
@@ -0,0 +1,121 @@
+//! Opens input files, which may be gzip or zstd compressed or `-` for
+//! stdin.  Compressed input is decoded while it is read, nothing is written
+//! to disk.
+
+use flate2::bufread::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The path that reads stdin.
+pub const STDIN: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// By the `.gz` or `.zst` extension.
+    fn from_extension(path: &str) -> Option<Compression> {
+        match Path::new(path).extension()?.to_str()? {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// By the first bytes of the input.
+    fn from_magic(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Whether `path` is an uncompressed file, which can be split into byte
+/// ranges and has a known length.
+pub fn is_plain_file(path: &str) -> io::Result<bool> {
+    if path == STDIN || Compression::from_extension(path).is_some() {
+        return Ok(false);
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(Compression::from_magic(reader.fill_buf()?) == Compression::None)
+}
+
+/// Opens `path` for reading, decompressing gzip and zstd input.  `-` reads
+/// stdin.
+pub fn open(path: &str) -> io::Result<Box<dyn BufRead + Send>> {
+    if path == STDIN {
+        return decode(BufReader::new(io::stdin()), None);
+    }
+    let reader = BufReader::new(File::open(path)?);
+    decode(reader, Compression::from_extension(path))
+}
+
+/// Wraps `reader` in a decoder for `compression`, or for the magic bytes it
+/// starts with if `None`.
+fn decode<R>(mut reader: R, compression: Option<Compression>) -> io::Result<Box<dyn BufRead + Send>>
+where
+    R: BufRead + Send + 'static,
+{
+    let compression = match compression {
+        Some(c) => c,
+        None => Compression::from_magic(reader.fill_buf()?),
+    };
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+#[cfg(test)]
+mod input_tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::fs;
+    use std::io::{Read, Write};
+
+    fn read_all(path: &str) -> String {
+        let mut contents = String::new();
+        open(path).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_compressed_input() {
+        let contents = "1 2\n2 3\n";
+        let dir = std::env::temp_dir();
+
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(contents.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+        let zst = zstd::encode_all(contents.as_bytes(), 0).unwrap();
+
+        let paths = [
+            (dir.join("deepwalk_input.txt.gz"), gz.clone()),
+            // Detected by the magic bytes alone.
+            (dir.join("deepwalk_input_gz.txt"), gz),
+            (dir.join("deepwalk_input.txt.zst"), zst.clone()),
+            (dir.join("deepwalk_input_zst.txt"), zst),
+            (dir.join("deepwalk_input.txt"), contents.as_bytes().to_vec()),
+        ];
+        for (path, bytes) in &paths {
+            fs::write(path, bytes).unwrap();
+            let path = path.to_str().unwrap();
+            assert_eq!(read_all(path), contents);
+            assert_eq!(is_plain_file(path).unwrap(), path.ends_with("input.txt"));
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
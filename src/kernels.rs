@@ -0,0 +1,262 @@
+//! Dot, axpy and scale over contiguous slices.
+//!
+//! On x86_64 the AVX2/FMA versions are selected at runtime when the CPU
+//! supports them, everywhere else the scalar fallbacks are used.  The scalar
+//! versions accumulate into several lanes so the compiler can vectorize them.
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+#[cfg(target_arch = "x86_64")]
+fn has_avx2_fma() -> bool {
+    is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma")
+}
+
+pub fn dot_f32(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2_fma() {
+            // SAFETY: the CPU supports AVX2 and FMA, and the slices have
+            // equal lengths as asserted above, so the unaligned loads and
+            // stores stay in bounds.
+            return unsafe { dot_f32_avx2(a, b) };
+        }
+    }
+    dot_scalar_f32(a, b)
+}
+
+pub fn dot_f64(a: &[f64], b: &[f64]) -> f64 {
+    assert_eq!(a.len(), b.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2_fma() {
+            // SAFETY: the CPU supports AVX2 and FMA, and the slices have
+            // equal lengths as asserted above, so the unaligned loads and
+            // stores stay in bounds.
+            return unsafe { dot_f64_avx2(a, b) };
+        }
+    }
+    dot_scalar_f64(a, b)
+}
+
+/// `y += alpha * x`
+pub fn axpy_f32(alpha: f32, x: &[f32], y: &mut [f32]) {
+    assert_eq!(x.len(), y.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2_fma() {
+            // SAFETY: the CPU supports AVX2 and FMA, and the slices have
+            // equal lengths as asserted above, so the unaligned loads and
+            // stores stay in bounds.
+            return unsafe { axpy_f32_avx2(alpha, x, y) };
+        }
+    }
+    axpy_scalar(alpha, x, y)
+}
+
+/// `y += alpha * x`
+pub fn axpy_f64(alpha: f64, x: &[f64], y: &mut [f64]) {
+    assert_eq!(x.len(), y.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2_fma() {
+            // SAFETY: the CPU supports AVX2 and FMA, and the slices have
+            // equal lengths as asserted above, so the unaligned loads and
+            // stores stay in bounds.
+            return unsafe { axpy_f64_avx2(alpha, x, y) };
+        }
+    }
+    axpy_scalar(alpha, x, y)
+}
+
+/// `x *= alpha`
+pub fn scale<T: Copy + std::ops::MulAssign>(alpha: T, x: &mut [T]) {
+    for v in x.iter_mut() {
+        *v *= alpha;
+    }
+}
+
+fn dot_scalar_f32(a: &[f32], b: &[f32]) -> f32 {
+    let mut acc = [0.0f32; 8];
+    let chunks = a.len() / 8 * 8;
+    for (ca, cb) in a[..chunks].chunks_exact(8).zip(b[..chunks].chunks_exact(8)) {
+        for i in 0..8 {
+            acc[i] += ca[i] * cb[i];
+        }
+    }
+    let mut sum: f32 = acc.iter().sum();
+    for i in chunks..a.len() {
+        sum += a[i] * b[i];
+    }
+    sum
+}
+
+fn dot_scalar_f64(a: &[f64], b: &[f64]) -> f64 {
+    let mut acc = [0.0f64; 4];
+    let chunks = a.len() / 4 * 4;
+    for (ca, cb) in a[..chunks].chunks_exact(4).zip(b[..chunks].chunks_exact(4)) {
+        for i in 0..4 {
+            acc[i] += ca[i] * cb[i];
+        }
+    }
+    let mut sum: f64 = acc.iter().sum();
+    for i in chunks..a.len() {
+        sum += a[i] * b[i];
+    }
+    sum
+}
+
+fn axpy_scalar<T>(alpha: T, x: &[T], y: &mut [T])
+where
+    T: Copy + std::ops::Mul<Output = T> + std::ops::AddAssign,
+{
+    for (yi, xi) in y.iter_mut().zip(x) {
+        *yi += alpha * *xi;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn dot_f32_avx2(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len();
+    let chunks = n / 16 * 16;
+    let mut acc0 = _mm256_setzero_ps();
+    let mut acc1 = _mm256_setzero_ps();
+    let mut i = 0;
+    while i < chunks {
+        acc0 = _mm256_fmadd_ps(
+            _mm256_loadu_ps(a.as_ptr().add(i)),
+            _mm256_loadu_ps(b.as_ptr().add(i)),
+            acc0,
+        );
+        acc1 = _mm256_fmadd_ps(
+            _mm256_loadu_ps(a.as_ptr().add(i + 8)),
+            _mm256_loadu_ps(b.as_ptr().add(i + 8)),
+            acc1,
+        );
+        i += 16;
+    }
+    let mut lanes = [0.0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), _mm256_add_ps(acc0, acc1));
+    let mut sum: f32 = lanes.iter().sum();
+    for j in chunks..n {
+        sum += a[j] * b[j];
+    }
+    sum
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn dot_f64_avx2(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len();
+    let chunks = n / 8 * 8;
+    let mut acc0 = _mm256_setzero_pd();
+    let mut acc1 = _mm256_setzero_pd();
+    let mut i = 0;
+    while i < chunks {
+        acc0 = _mm256_fmadd_pd(
+            _mm256_loadu_pd(a.as_ptr().add(i)),
+            _mm256_loadu_pd(b.as_ptr().add(i)),
+            acc0,
+        );
+        acc1 = _mm256_fmadd_pd(
+            _mm256_loadu_pd(a.as_ptr().add(i + 4)),
+            _mm256_loadu_pd(b.as_ptr().add(i + 4)),
+            acc1,
+        );
+        i += 8;
+    }
+    let mut lanes = [0.0f64; 4];
+    _mm256_storeu_pd(lanes.as_mut_ptr(), _mm256_add_pd(acc0, acc1));
+    let mut sum: f64 = lanes.iter().sum();
+    for j in chunks..n {
+        sum += a[j] * b[j];
+    }
+    sum
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn axpy_f32_avx2(alpha: f32, x: &[f32], y: &mut [f32]) {
+    let n = x.len();
+    let chunks = n / 8 * 8;
+    let a = _mm256_set1_ps(alpha);
+    let mut i = 0;
+    while i < chunks {
+        let yp = y.as_mut_ptr().add(i);
+        let r = _mm256_fmadd_ps(a, _mm256_loadu_ps(x.as_ptr().add(i)), _mm256_loadu_ps(yp));
+        _mm256_storeu_ps(yp, r);
+        i += 8;
+    }
+    for j in chunks..n {
+        y[j] += alpha * x[j];
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn axpy_f64_avx2(alpha: f64, x: &[f64], y: &mut [f64]) {
+    let n = x.len();
+    let chunks = n / 4 * 4;
+    let a = _mm256_set1_pd(alpha);
+    let mut i = 0;
+    while i < chunks {
+        let yp = y.as_mut_ptr().add(i);
+        let r = _mm256_fmadd_pd(a, _mm256_loadu_pd(x.as_ptr().add(i)), _mm256_loadu_pd(yp));
+        _mm256_storeu_pd(yp, r);
+        i += 4;
+    }
+    for j in chunks..n {
+        y[j] += alpha * x[j];
+    }
+}
+
+#[cfg(test)]
+mod kernels_tests {
+    use super::*;
+
+    fn vectors(n: usize) -> (Vec<f64>, Vec<f64>) {
+        let a = (0..n).map(|i| (i as f64 * 0.37).sin()).collect();
+        let b = (0..n).map(|i| (i as f64 * 0.11).cos()).collect();
+        (a, b)
+    }
+
+    #[test]
+    fn test_dot() {
+        for n in [0, 1, 3, 4, 7, 8, 15, 16, 17, 128, 131].iter() {
+            let (a, b) = vectors(*n);
+            let expected: f64 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+            assert!((dot_f64(&a, &b) - expected).abs() < 1e-10);
+            assert!((dot_scalar_f64(&a, &b) - expected).abs() < 1e-10);
+
+            let a32: Vec<f32> = a.iter().map(|x| *x as f32).collect();
+            let b32: Vec<f32> = b.iter().map(|x| *x as f32).collect();
+            assert!((dot_f32(&a32, &b32) as f64 - expected).abs() < 1e-4);
+            assert!((dot_scalar_f32(&a32, &b32) as f64 - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_axpy_and_scale() {
+        for n in [0, 1, 5, 8, 13, 128].iter() {
+            let (x, y) = vectors(*n);
+            let expected: Vec<f64> = x.iter().zip(&y).map(|(a, b)| b + 0.5 * a).collect();
+
+            let mut y64 = y.clone();
+            axpy_f64(0.5, &x, &mut y64);
+            let mut y32: Vec<f32> = y.iter().map(|v| *v as f32).collect();
+            let x32: Vec<f32> = x.iter().map(|v| *v as f32).collect();
+            axpy_f32(0.5, &x32, &mut y32);
+            for i in 0..*n {
+                assert!((y64[i] - expected[i]).abs() < 1e-12);
+                assert!((y32[i] as f64 - expected[i]).abs() < 1e-5);
+            }
+
+            scale(2.0, &mut y64);
+            for i in 0..*n {
+                assert!((y64[i] - 2.0 * expected[i]).abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -1,47 +1,470 @@
 pub mod activation_functions;
+pub mod alias;
+pub mod attributes;
+pub mod cache;
 pub mod config;
+pub mod early_stopping;
+pub mod embeddings;
+pub mod error;
+pub mod float;
 pub mod graph;
+pub mod graphml;
 pub mod huffman_tree;
+pub mod input;
+pub mod kernels;
+pub mod line;
+pub mod loader;
+pub mod logging;
+pub mod matrix_market;
+pub mod metrics;
 pub mod model;
 pub mod model_concurrent;
+pub mod negative_sampling;
+pub mod output_layer;
+pub mod spectral;
+pub mod stats;
+pub mod subsampling;
 
+use crate::config::{Architecture, WalkMode};
+use crate::early_stopping::{EarlyStopping, Evaluation};
+use crate::error::DeepwalkError;
+use crate::float::Float;
+use crate::metrics::{MetricsLog, NormStats, Progress, Record};
+use crate::output_layer::OutputLayer;
 use crossbeam::sync::WaitGroup;
+use log::{debug, info, warn};
+use nalgebra::DMatrix;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use std::cmp;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time::Instant;
 
-pub fn deepwalk(args: Vec<String>) {
-    let config = config::Config::new(&args[1]);
+pub fn deepwalk(args: Vec<String>) -> Result<(), DeepwalkError> {
+    let usage = || DeepwalkError::Config("usage: deepwalk [stats] config.json".to_string());
+    let (stats, config_file) = match args.get(1).map(String::as_str) {
+        Some("stats") => (true, args.get(2).ok_or_else(usage)?),
+        Some(_) => (false, &args[1]),
+        None => return Err(usage()),
+    };
+    let config = config::Config::new(config_file)?;
+    logging::init(config.log_level(), config.progress());
 
-    println!("Run Starting...");
+    if stats {
+        let stats = graph_stats(&config)?;
+        let json = serde_json::to_string_pretty(&stats).map_err(std::io::Error::from)?;
+        println!("{}", json);
+        return Ok(());
+    }
+    info!("Run starting");
     let now = Instant::now();
+    let g = load_graph(&config)?;
+    info!("Graph built with {} nodes", g.num_nodes());
+    run(g, config)?;
+    info!("Run took {} seconds", now.elapsed().as_secs());
+    Ok(())
+}
+
+/// Loads `input_file` with the loader options of `config`, without the
+/// graph cache.  The report counts duplicate edges and self loops before
+/// the edge policy.
+fn load_input(
+    config: &config::Config,
+) -> Result<(graph::Graph, loader::LoadReport), DeepwalkError> {
     let mut g = graph::Graph::new();
-    g.build_graph_from_file(config.input_file());
-    println!("...graph built..");
-    let hm = huffman_tree::HuffmanTree::new(g.get_node_iter());
-    println!("...huffman tree built...");
+    let report = loader::load_graph(
+        &mut g,
+        config.input_file(),
+        config.input_format(),
+        &loader::EdgeListFormat::from_config(config),
+        graph::EdgePolicy::from_config(config),
+    )?;
+    Ok((g, report))
+}
+
+/// The statistics of `input_file` loaded as for a run.  The input is parsed
+/// even if a graph cache is set, so the duplicate edges and self loops it
+/// reports are those of the input, not those left by the edge policy.
+pub fn graph_stats(config: &config::Config) -> Result<stats::GraphStats, DeepwalkError> {
+    let (g, report) = load_input(config)?;
+    Ok(g.stats().with_load_report(&report))
+}
+
+/// Loads `input_file` with the loader options of `config`, through the
+/// graph cache if one is set, and attaches the `attribute_file`.
+pub fn load_graph(config: &config::Config) -> Result<graph::Graph, DeepwalkError> {
+    let format = loader::EdgeListFormat::from_config(config);
+    let policy = graph::EdgePolicy::from_config(config);
+    let load = || load_input(config).map(|(g, _)| g);
+    let mut g = if config.graph_cache().is_empty() {
+        load()?
+    } else {
+        let options = format!("{:?} {:?} {:?}", config.input_format(), format, policy);
+        cache::load_or_build(config.graph_cache(), config.input_file(), &options, load)?
+    };
+    if !config.attribute_file().is_empty() {
+        let attributes = attributes::NodeAttributes::load(config.attribute_file(), &format)?;
+        for name in config.output_attributes() {
+            if attributes.column(name).is_none() {
+                return Err(DeepwalkError::Config(format!(
+                    "output_attributes: {} has no attribute {:?}",
+                    config.attribute_file(),
+                    name
+                )));
+            }
+        }
+        let missing = g.set_attributes(attributes);
+        if missing > 0 {
+            warn!("{} nodes have no attributes", missing);
+        }
+    }
+    Ok(g)
+}
+
+/// Trains on an already built graph and writes the weight and perf files.
+///
+/// Components outside `largest_component` and `min_component_size` are
+/// removed first.  Graphs made of isolated nodes or self loops train to
+/// defined outputs, an empty graph is an error.
+pub fn run(mut g: graph::Graph, config: config::Config) -> Result<(), DeepwalkError> {
+    if config.largest_component() || config.min_component_size() > 1 {
+        let (largest, min_size) = (config.largest_component(), config.min_component_size());
+        let removed =
+            g.retain_components(|component, size| size >= min_size && !(largest && component > 0));
+        info!(
+            "Removed {} nodes outside the kept components, {} nodes left",
+            removed,
+            g.num_nodes()
+        );
+    }
+    if g.num_nodes() == 0 {
+        return Err(DeepwalkError::EmptyGraph);
+    }
+    // Before a held out split can change the components.
+    let labels = embeddings::node_labels(&g, &config);
+    match config.method() {
+        config::Method::Deepwalk => (),
+        config::Method::Line => {
+            return match config.precision() {
+                config::Precision::F32 => line::run::<f32>(g, &labels, config),
+                config::Precision::F64 => line::run::<f64>(g, &labels, config),
+            };
+        }
+        config::Method::Netmf | config::Method::LaplacianEigenmaps => {
+            return spectral::run(&g, &labels, &config);
+        }
+    }
+    let early_stopping = EarlyStopping::from_config(&config, &mut g, &mut thread_rng());
+    if let Some(holdout) = early_stopping.as_ref().and_then(|es| es.holdout()) {
+        info!("Held out {} edges", holdout.len());
+    }
+    let frequencies =
+        if config.sample() > 0.0 || config.huffman_weights() == config::HuffmanWeights::Walks {
+            walk_frequencies(&g, &config)
+        } else {
+            HashMap::new()
+        };
+    let output_layer = match config.objective() {
+        config::Objective::HierarchicalSoftmax => {
+            let hm = build_huffman_tree(&g, &frequencies, &config)?;
+            debug!(
+                "Huffman tree built with {} inner nodes, max code length {}",
+                hm.num_inner_nodes(),
+                hm.max_code_length()
+            );
+            OutputLayer::HierarchicalSoftmax(hm)
+        }
+        config::Objective::NegativeSampling => OutputLayer::NegativeSampling {
+            sampler: negative_sampling::NegativeSampler::from_graph(&g),
+            negative: config.negative(),
+            num_nodes: g.num_nodes(),
+        },
+    };
+    let subsampler = subsampling::Subsampler::new(&g, &frequencies, config.sample());
+    match config.precision() {
+        config::Precision::F32 => {
+            train_model::<f32>(output_layer, g, labels, subsampler, config, early_stopping)
+        }
+        config::Precision::F64 => {
+            train_model::<f64>(output_layer, g, labels, subsampler, config, early_stopping)
+        }
+    }
+}
+
+fn train_model<T: Float>(
+    output_layer: OutputLayer,
+    g: graph::Graph,
+    labels: Vec<String>,
+    subsampler: subsampling::Subsampler,
+    config: config::Config,
+    early_stopping: Option<EarlyStopping>,
+) -> Result<(), DeepwalkError> {
     if config.nthreads() > 1 {
-        let model = model_concurrent::ConcurrentModel::new(g.num_nodes(), config.vector_dim());
-        train_concurrent(Arc::new(model), Arc::new(hm), Arc::new(g), Arc::new(config));
+        let mut model = model_concurrent::ConcurrentModel::<T>::new(
+            g.num_nodes(),
+            output_layer.num_outputs(),
+            config.vector_dim(),
+        );
+        model.set_sigmoid_mode(config.sigmoid());
+        model.set_objective(config.objective());
+        train_concurrent(
+            Arc::new(model),
+            Arc::new(output_layer),
+            Arc::new(g),
+            labels,
+            Arc::new(subsampler),
+            Arc::new(config),
+            early_stopping,
+        )?;
     } else {
-        let model = model::Model::new(g.num_nodes(), config.vector_dim());
-        train(model, hm, g, config);
+        let model = new_model::<T>(&g, &output_layer, &config);
+        train(
+            model,
+            output_layer,
+            g,
+            labels,
+            subsampler,
+            config,
+            early_stopping,
+        )?;
+    }
+    Ok(())
+}
+
+/// The single threaded model for `g`, with the configured sigmoid and
+/// objective.
+fn new_model<T: Float>(
+    g: &graph::Graph,
+    output_layer: &OutputLayer,
+    config: &config::Config,
+) -> model::Model<T> {
+    let mut model = model::Model::new(
+        g.num_nodes(),
+        output_layer.num_outputs(),
+        config.vector_dim(),
+    );
+    model.set_sigmoid_mode(config.sigmoid());
+    model.set_objective(config.objective());
+    model
+}
+
+/// The window around position `v` of a walk of length `len`.
+fn window_bounds(v: usize, window_size: usize, len: usize) -> (usize, usize) {
+    (
+        v.saturating_sub(window_size),
+        cmp::min(v + window_size, len),
+    )
+}
+
+/// Collects the nodes of `walk[start..end]` other than position `v`.
+fn fill_contexts(walk: &[usize], v: usize, start: usize, end: usize, contexts: &mut Vec<usize>) {
+    contexts.clear();
+    for (u, &context) in walk.iter().enumerate().take(end).skip(start) {
+        if u != v {
+            contexts.push(context);
+        }
+    }
+}
+
+/// Samples the training walk from `node` in the configured walk mode, non
+/// backtracking if configured, as node indices and subsamples it.  Returns
+/// the walk and the number of subsampled nodes.  An `app` walk is the
+/// source followed by its stopping nodes, and only the stopping nodes are
+/// subsampled.
+fn sample_walk<R: Rng>(
+    graph: &graph::Graph,
+    node: &graph::NodeID,
+    subsampler: &subsampling::Subsampler,
+    config: &config::Config,
+    rng: &mut R,
+) -> (Vec<usize>, usize) {
+    let (walk_len, alpha) = (config.walk_length(), config.alpha());
+    let non_backtracking = config.non_backtracking();
+    let mut walk = match config.walk_mode() {
+        WalkMode::Uniform if non_backtracking => {
+            graph.walk_to_idx(&graph.non_backtracking_walk(node, walk_len))
+        }
+        WalkMode::Uniform => graph.walk_to_idx(&graph.random_walk(node, walk_len)),
+        WalkMode::Restart => graph.walk_to_idx(&graph.random_walk_with_restart(
+            node,
+            walk_len,
+            alpha,
+            non_backtracking,
+        )),
+        WalkMode::App => {
+            let stops = graph.stopping_nodes(node, walk_len, alpha, non_backtracking);
+            let mut stops = graph.walk_to_idx(&stops);
+            let subsampled = subsampler.subsample(&mut stops, rng);
+            if stops.is_empty() {
+                return (stops, subsampled);
+            }
+            let mut walk = vec![graph.get_node_idx(node).copied().unwrap()];
+            walk.append(&mut stops);
+            return (walk, subsampled);
+        }
+    };
+    let subsampled = subsampler.subsample(&mut walk, rng);
+    (walk, subsampled)
+}
+
+/// Counts how often each node occurs in one training walk from every node,
+/// sampled as by `sample_walk` but without subsampling.
+fn walk_frequencies(graph: &graph::Graph, config: &config::Config) -> HashMap<graph::NodeID, u64> {
+    let no_subsampling = subsampling::Subsampler::new(graph, &HashMap::new(), 0.0);
+    let node_ids = graph.node_ids_by_idx();
+    let mut rng = thread_rng();
+    let mut frequencies = HashMap::with_capacity(graph.num_nodes());
+    for node in graph.get_node_iter() {
+        let (walk, _) = sample_walk(graph, &node.get_id(), &no_subsampling, config, &mut rng);
+        for idx in walk {
+            *frequencies.entry(node_ids[idx]).or_insert(0) += 1;
+        }
+    }
+    frequencies
+}
+
+/// Collects the contexts of position `v` of `walk`, the window around it.
+/// In an `app` walk the source and its stopping nodes are each other's
+/// contexts instead.
+fn walk_contexts(
+    walk_mode: WalkMode,
+    walk: &[usize],
+    v: usize,
+    window_size: usize,
+    contexts: &mut Vec<usize>,
+) {
+    if walk_mode == WalkMode::App {
+        contexts.clear();
+        match v {
+            0 => contexts.extend_from_slice(&walk[1..]),
+            _ => contexts.push(walk[0]),
+        }
+        return;
     }
-    println!("Run took {} seconds", now.elapsed().as_secs());
+    let (start, end) = window_bounds(v, window_size, walk.len());
+    fill_contexts(walk, v, start, end, contexts);
+}
+
+/// Builds the tree with leaf `i` for the node with index `i`.
+fn build_huffman_tree(
+    graph: &graph::Graph,
+    walk_frequencies: &HashMap<graph::NodeID, u64>,
+    config: &config::Config,
+) -> Result<huffman_tree::HuffmanTree, DeepwalkError> {
+    let min_length = huffman_tree::min_code_length(graph.num_nodes());
+    if config.max_code_length() > 0 && config.max_code_length() < min_length {
+        return Err(DeepwalkError::Config(format!(
+            "max_code_length {} is too short for {} nodes, at least {} is needed",
+            config.max_code_length(),
+            graph.num_nodes(),
+            min_length
+        )));
+    }
+    let file_frequencies;
+    let frequencies = match config.huffman_weights() {
+        config::HuffmanWeights::Degree => {
+            return Ok(huffman_tree::HuffmanTree::new(
+                graph,
+                config.max_code_length(),
+            ));
+        }
+        config::HuffmanWeights::Walks => walk_frequencies,
+        config::HuffmanWeights::File => {
+            file_frequencies = graph::Graph::read_node_frequencies(config.frequency_file())?;
+            &file_frequencies
+        }
+    };
+    // Nodes that were never observed still need a leaf.
+    Ok(huffman_tree::HuffmanTree::from_frequencies(
+        graph
+            .node_ids_by_idx()
+            .into_iter()
+            .map(|id| (id, *frequencies.get(&id).unwrap_or(&0) as i64)),
+        config.max_code_length(),
+    ))
 }
-pub fn train(
-    mut model: model::Model,
-    huffman_tree: huffman_tree::HuffmanTree,
+
+/// The perf file header, with a validation column when early stopping.
+fn perf_header(early_stopping: &Option<EarlyStopping>) -> &'static str {
+    match early_stopping {
+        Some(_) => "iteration learning_rate error time subsampled validation",
+        None => "iteration learning_rate error time subsampled",
+    }
+}
+
+/// Runs the early stopping evaluation for iteration `iter` and snapshots the
+/// input and output vectors into `best` when they improved.
+fn evaluate_iteration<T, I, O>(
+    early_stopping: &mut Option<EarlyStopping>,
+    iter: usize,
+    error: f64,
+    input: I,
+    output: O,
+    best: &mut Option<(DMatrix<T>, DMatrix<T>)>,
+) -> Evaluation
+where
+    T: Float,
+    I: Fn() -> DMatrix<T>,
+    O: Fn() -> DMatrix<T>,
+{
+    let es = match early_stopping {
+        Some(es) => es,
+        None => return Evaluation::Skipped,
+    };
+    let evaluation = es.evaluate(iter, error, &input);
+    if let Evaluation::Improved(_) = evaluation {
+        *best = Some((input(), output()));
+    }
+    if let Evaluation::Stop(_) = evaluation {
+        info!(
+            "Stopping early at iteration {}, keeping iteration {}",
+            iter,
+            es.best_iteration().unwrap()
+        );
+    }
+    evaluation
+}
+
+/// The validation column of a perf file row, empty without early stopping.
+fn validation_column(early_stopping: &Option<EarlyStopping>, evaluation: Evaluation) -> String {
+    match (early_stopping, evaluation.value()) {
+        (None, _) => String::new(),
+        (Some(_), Some(value)) => format!(" {}", value),
+        (Some(_), None) => " -".to_string(),
+    }
+}
+
+/// Writes the weight file and the requested output vectors from a snapshot.
+fn write_snapshot<T: Float>(
+    (input, output): &(DMatrix<T>, DMatrix<T>),
+    output_layer: &OutputLayer,
+    labels: &[String],
+    config: &config::Config,
+) -> Result<(), DeepwalkError> {
+    embeddings::write_vectors(&config.weight_file(), labels, input)?;
+    if config.exports_output_vectors() {
+        embeddings::write_output_vectors(input, output, output_layer, labels, config)?;
+    }
+    Ok(())
+}
+
+pub fn train<T: Float>(
+    mut model: model::Model<T>,
+    output_layer: OutputLayer,
     graph: graph::Graph,
+    labels: Vec<String>,
+    subsampler: subsampling::Subsampler,
     config: config::Config,
-) {
-    let walk_len = config.walk_length();
+    mut early_stopping: Option<EarlyStopping>,
+) -> Result<(), DeepwalkError> {
+    let walk_mode = config.walk_mode();
     let window_size = config.window_size();
+    let mean = !config.cbow_sum();
+    let (mut points, mut codes, mut contexts) = (Vec::new(), Vec::new(), Vec::new());
 
     let mut node_ids = Vec::new();
     for i in graph.get_node_iter() {
@@ -50,56 +473,122 @@ pub fn train(
 
     let mut lr = config.learning_rate();
     let start_lr = 0.025;
-    let mut f = File::create("perf.txt").expect("Unable to create output file for perf");
-    writeln!(f, "iteration learning_rate error time").expect("Unable to write to perf file");
+    let mut f = File::create(config.perf_file())?;
+    writeln!(f, "{}", perf_header(&early_stopping))?;
+    let mut metrics = MetricsLog::from_config(&config)?;
+    let metrics_every = metrics.as_ref().map_or(0, |m| m.every());
     let now = Instant::now();
+    let mut best = None;
+    let total_walks = (config.num_iterations() * node_ids.len()) as u64;
+    let bar = logging::progress_bar(total_walks, "training", "walks");
 
     for iter in 0..config.num_iterations() {
         let mut rng = thread_rng();
         node_ids.shuffle(&mut rng);
-        let mut error = 0.0;
+        let epoch_start = Instant::now();
+        let mut progress = Progress::default();
         for node in &node_ids {
-            let walk = graph.random_walk(node, walk_len);
-            for v in 0..walk_len {
-                let target = graph.get_node_idx(&walk[v]).unwrap();
-                let start = if window_size > v { 0 } else { v - window_size };
-                for u in start..cmp::min(v + window_size, walk_len) {
-                    if u != v {
-                        let outcomes = huffman_tree.get_indices_and_turns(&walk[u]);
-                        error += model.step(*target, outcomes, lr);
+            let (walk, subsampled) = sample_walk(&graph, node, &subsampler, &config, &mut rng);
+            let (mut walk_error, mut pairs) = (0.0, 0);
+            for (v, &target) in walk.iter().enumerate() {
+                walk_contexts(walk_mode, &walk, v, window_size, &mut contexts);
+                match config.architecture() {
+                    Architecture::SkipGram => {
+                        for &context in &contexts {
+                            let (p, c) =
+                                output_layer.outputs(context, &mut rng, &mut points, &mut codes);
+                            walk_error += model.step(target, p, c, lr);
+                            pairs += 1;
+                        }
+                    }
+                    Architecture::Cbow => {
+                        if !contexts.is_empty() {
+                            let (p, c) =
+                                output_layer.outputs(target, &mut rng, &mut points, &mut codes);
+                            walk_error += model.step_cbow(&contexts, p, c, lr, mean);
+                            pairs += 1;
+                        }
                     }
                 }
             }
+            progress.add_walk(pairs, walk_error, subsampled);
+            bar.inc(1);
+            if let Some(metrics) = metrics.as_mut() {
+                if metrics::step_due(metrics_every, progress.walks) {
+                    metrics.write(&Record::step(iter, &progress, epoch_start, now, lr))?;
+                }
+            }
         }
-        if iter % 1 == 0 {
-            println!(
-                "Iteration: {}\nLearning Rate: {}\nError: {}",
+        progress.busy = epoch_start.elapsed();
+        let (error, subsampled) = (progress.loss_per_pair(), progress.subsampled);
+        info!(
+            "Iteration {}: learning rate {}, error {}, subsampled {}",
+            iter, lr, error, subsampled
+        );
+        if let Some(metrics) = metrics.as_mut() {
+            let norms = NormStats::from_norms(model.input_norms());
+            metrics.write(&Record::epoch(
                 iter,
+                &progress,
+                epoch_start,
+                now,
                 lr,
-                error / (node_ids.len() as f64)
-            );
+                norms,
+                1,
+            ))?;
         }
 
+        let evaluation = evaluate_iteration(
+            &mut early_stopping,
+            iter,
+            error,
+            || model.input_matrix(),
+            || model.output_matrix(),
+            &mut best,
+        );
+
         writeln!(
             f,
-            "{} {} {} {}",
+            "{} {} {} {} {}{}",
             iter,
             lr,
             error / (node_ids.len() as f64),
-            now.elapsed().as_secs()
-        )
-        .expect("Unable to write to perf file");
+            now.elapsed().as_secs(),
+            subsampled,
+            validation_column(&early_stopping, evaluation)
+        )?;
 
-        lr = lr - start_lr / (config.num_iterations() as f64);
+        if let Evaluation::Stop(_) = evaluation {
+            break;
+        }
+        lr -= start_lr / (config.num_iterations() as f64);
     }
+    bar.finish_and_clear();
+    if let Some(best) = best {
+        return write_snapshot(&best, &output_layer, &labels, &config);
+    }
+    model.write_weight_mat(&config.weight_file(), &labels)?;
+    if config.exports_output_vectors() {
+        embeddings::write_output_vectors(
+            &model.input_matrix(),
+            &model.output_matrix(),
+            &output_layer,
+            &labels,
+            &config,
+        )?;
+    }
+    Ok(())
 }
 
-pub fn train_concurrent(
-    model: Arc<model_concurrent::ConcurrentModel>,
-    huffman_tree: Arc<huffman_tree::HuffmanTree>,
+pub fn train_concurrent<T: Float>(
+    model: Arc<model_concurrent::ConcurrentModel<T>>,
+    output_layer: Arc<OutputLayer>,
     graph: Arc<graph::Graph>,
+    labels: Vec<String>,
+    subsampler: Arc<subsampling::Subsampler>,
     config: Arc<config::Config>,
-) {
+    mut early_stopping: Option<EarlyStopping>,
+) -> Result<(), DeepwalkError> {
     let mut node_ids = Vec::new();
     for i in graph.get_node_iter() {
         node_ids.push(i.get_id());
@@ -107,15 +596,24 @@ pub fn train_concurrent(
     let mut lr = config.learning_rate();
     let start_lr = lr;
 
-    let mut f = File::create(config.perf_file()).expect("Unable to create output file for perf");
-    writeln!(f, "iteration learning_rate error time").expect("Unable to write to perf file");
+    let mut f = File::create(config.perf_file())?;
+    writeln!(f, "{}", perf_header(&early_stopping))?;
+    let mut metrics = MetricsLog::from_config(&config)?;
+    let metrics_every = metrics.as_ref().map_or(0, |m| m.every());
     let now = Instant::now();
+    let mut best = None;
+    let total_walks = (config.num_iterations() * node_ids.len()) as u64;
+    let bar = logging::progress_bar(total_walks, "training", "walks");
 
     for iter in 0..config.num_iterations() {
         let mut rng = thread_rng();
         node_ids.shuffle(&mut rng);
 
-        let error = Arc::new(Mutex::new(0.0));
+        let epoch_start = Instant::now();
+        let progress = Arc::new(Mutex::new(Progress::default()));
+        // Step records are collected by the threads and written in order
+        // at the end of the iteration.
+        let steps = Arc::new(Mutex::new(Vec::new()));
         let tmp_nodes = Arc::new(RwLock::new(node_ids.to_vec()));
 
         let wg_iter = WaitGroup::new();
@@ -128,53 +626,705 @@ pub fn train_concurrent(
         for _ in 0..nthreads {
             let wg_iter = wg_iter.clone();
             let model = Arc::clone(&model);
-            let error = Arc::clone(&error);
+            let progress = Arc::clone(&progress);
+            let steps = Arc::clone(&steps);
             let learning_rate = lr;
             let tmp_nodes = Arc::clone(&tmp_nodes);
-            let huffman_tree = Arc::clone(&huffman_tree);
+            let output_layer = Arc::clone(&output_layer);
             let graph = Arc::clone(&graph);
+            let subsampler = Arc::clone(&subsampler);
             let config = Arc::clone(&config);
+            let bar = bar.clone();
             thread::spawn(move || {
-                let walk_len = config.walk_length();
+                let thread_start = Instant::now();
+                let walk_mode = config.walk_mode();
                 let window_size = config.window_size();
-                while tmp_nodes.read().unwrap().len() > 0 {
+                let mut rng = thread_rng();
+                let mean = !config.cbow_sum();
+                let mut buffers = model_concurrent::StepBuffers::new(config.vector_dim());
+                let (mut points, mut codes, mut contexts) = (Vec::new(), Vec::new(), Vec::new());
+                while !tmp_nodes.read().unwrap().is_empty() {
                     let node = tmp_nodes.write().unwrap().pop();
-                    match node {
-                        Some(node) => {
-                            let walk = graph.random_walk(&node, walk_len);
-                            for (i, target) in walk.iter().enumerate() {
-                                let start = if window_size > i { 0 } else { i - window_size };
-                                for u in start..cmp::min(i + window_size, walk_len) {
-                                    if u != i {
-                                        model.step(
-                                            *graph.get_node_idx(target).unwrap(),
-                                            &huffman_tree.get_indices_and_turns(&walk[u]),
+                    if let Some(node) = node {
+                        let (walk, subsampled) =
+                            sample_walk(&graph, &node, &subsampler, &config, &mut rng);
+                        let (mut walk_error, mut pairs) = (0.0, 0);
+                        for (i, &target) in walk.iter().enumerate() {
+                            walk_contexts(walk_mode, &walk, i, window_size, &mut contexts);
+                            match config.architecture() {
+                                Architecture::SkipGram => {
+                                    for &context in &contexts {
+                                        let (p, c) = output_layer.outputs(
+                                            context,
+                                            &mut rng,
+                                            &mut points,
+                                            &mut codes,
+                                        );
+                                        walk_error +=
+                                            model.step(target, p, c, learning_rate, &mut buffers);
+                                        pairs += 1;
+                                    }
+                                }
+                                Architecture::Cbow => {
+                                    if !contexts.is_empty() {
+                                        let (p, c) = output_layer.outputs(
+                                            target,
+                                            &mut rng,
+                                            &mut points,
+                                            &mut codes,
+                                        );
+                                        walk_error += model.step_cbow(
+                                            &contexts,
+                                            p,
+                                            c,
                                             learning_rate,
-                                            error.clone(),
-                                            config.vector_dim(),
+                                            mean,
+                                            &mut buffers,
                                         );
+                                        pairs += 1;
                                     }
                                 }
                             }
                         }
-                        None => (),
+                        bar.inc(1);
+                        // Every walk is counted right away, so step records
+                        // see the finished walks of all threads.
+                        let mut progress = progress.lock().unwrap();
+                        progress.add_walk(pairs, walk_error, subsampled);
+                        if metrics::step_due(metrics_every, progress.walks) {
+                            let record =
+                                Record::step(iter, &progress, epoch_start, now, learning_rate);
+                            steps.lock().unwrap().push(record);
+                        }
                     }
                 }
+                progress.lock().unwrap().busy += thread_start.elapsed();
                 drop(wg_iter);
             });
         }
         wg_iter.wait();
-        let err = *error.lock().unwrap() / (node_ids.len() as f64);
-        if iter % 1 == 0 {
-            println!("Iteration: {}", iter);
-            println!("Learning Rate: {}", lr);
-            println!("Error: {}", err);
+        let progress = progress.lock().unwrap().clone();
+        let err = progress.loss_per_pair();
+        let subsampled = progress.subsampled;
+        info!(
+            "Iteration {}: learning rate {}, error {}, subsampled {}",
+            iter, lr, err, subsampled
+        );
+        if let Some(metrics) = metrics.as_mut() {
+            for record in steps.lock().unwrap().iter() {
+                metrics.write(record)?;
+            }
+            let norms = NormStats::from_norms(model.input_norms());
+            metrics.write(&Record::epoch(
+                iter,
+                &progress,
+                epoch_start,
+                now,
+                lr,
+                norms,
+                nthreads,
+            ))?;
         }
-        //"iteration learning_rate error time");
-        writeln!(f, "{} {} {} {}", iter, lr, err, now.elapsed().as_secs())
-            .expect("Unable to write to perf file");
+        let evaluation = evaluate_iteration(
+            &mut early_stopping,
+            iter,
+            err,
+            || model.input_matrix(),
+            || model.output_matrix(),
+            &mut best,
+        );
+        //"iteration learning_rate error time subsampled validation");
+        writeln!(
+            f,
+            "{} {} {} {} {}{}",
+            iter,
+            lr,
+            err,
+            now.elapsed().as_secs(),
+            subsampled,
+            validation_column(&early_stopping, evaluation)
+        )?;
 
-        lr = lr - start_lr / (config.num_iterations() as f64);
+        if let Evaluation::Stop(_) = evaluation {
+            break;
+        }
+        lr -= start_lr / (config.num_iterations() as f64);
+    }
+    bar.finish_and_clear();
+    if let Some(best) = best {
+        return write_snapshot(&best, &output_layer, &labels, &config);
+    }
+    model.write_weight_mat(&config.weight_file(), &labels)?;
+    if config.exports_output_vectors() {
+        embeddings::write_output_vectors(
+            &model.input_matrix(),
+            &model.output_matrix(),
+            &output_layer,
+            &labels,
+            &config,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod deepwalk_tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn test_config(name: &str, nthreads: usize) -> config::Config {
+        test_config_with(name, nthreads, "{}")
+    }
+
+    /// A small config writing to the temp dir, the fields of the json object
+    /// `extra` override the defaults.
+    fn test_config_with(name: &str, nthreads: usize, extra: &str) -> config::Config {
+        let dir = env::temp_dir();
+        let mut file = serde_json::json!({
+            "learning_rate": 0.025,
+            "vector_dim": 4,
+            "walk_length": 5,
+            "window_size": 2,
+            "num_iterations": 2,
+            "input_file": "karate_network.txt",
+            "perf_file": dir.join(format!("{}_perf.txt", name)),
+            "weight_file": dir.join(format!("{}_weights.txt", name)),
+            "nthreads": nthreads
+        });
+        let extra: serde_json::Value = serde_json::from_str(extra).unwrap();
+        for (key, value) in extra.as_object().unwrap() {
+            file[key] = value.clone();
+        }
+        serde_json::from_value(file).unwrap()
+    }
+
+    fn read_weights(config: &config::Config) -> Vec<Vec<f64>> {
+        fs::read_to_string(config.weight_file())
+            .unwrap()
+            .lines()
+            .map(|l| l.split(' ').map(|x| x.parse::<f64>().unwrap()).collect())
+            .collect()
+    }
+
+    fn karate() -> graph::Graph {
+        let mut g = graph::Graph::new();
+        g.build_graph_from_file("karate_network.txt").unwrap();
+        g
+    }
+
+    /// The AUC of cosine similarity separating karate edges from all
+    /// non edges.
+    fn karate_link_auc(weights: &[Vec<f64>]) -> f64 {
+        let vectors: HashMap<i32, &[f64]> =
+            weights.iter().map(|w| (w[0] as i32, &w[1..])).collect();
+        let cosine = |a: &[f64], b: &[f64]| {
+            let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+            let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+            dot / (norm(a) * norm(b))
+        };
+        karate_link_auc_by(|a, b| cosine(vectors[&a], vectors[&b]))
+    }
+
+    /// The AUC of `score` separating karate edges from all non edges.
+    fn karate_link_auc_by<F: Fn(i32, i32) -> f64>(score: F) -> f64 {
+        let g = karate();
+        let mut ids: Vec<i32> = g.node_ids_by_idx();
+        ids.sort();
+        let (mut positive, mut negative) = (Vec::new(), Vec::new());
+        for (i, a) in ids.iter().enumerate() {
+            for b in &ids[i + 1..] {
+                let score = score(*a, *b);
+                if g.get_node(a).unwrap().get_neighbors().contains(b) {
+                    positive.push(score);
+                } else {
+                    negative.push(score);
+                }
+            }
+        }
+        let mut wins = 0.0;
+        for p in &positive {
+            for n in &negative {
+                if p > n {
+                    wins += 1.0;
+                } else if p == n {
+                    wins += 0.5;
+                }
+            }
+        }
+        wins / (positive.len() * negative.len()) as f64
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let config = test_config("deepwalk_empty", 1);
+        match run(graph::Graph::new(), config) {
+            Err(DeepwalkError::EmptyGraph) => (),
+            _ => panic!("expected an EmptyGraph error"),
+        }
+    }
+
+    #[test]
+    fn test_degenerate_graphs() {
+        for nthreads in [1, 4].iter() {
+            let name = format!("deepwalk_degenerate_{}", nthreads);
+
+            let mut g = graph::Graph::new();
+            g.build(vec![vec![1, 1]]);
+            run(g, test_config(&name, *nthreads)).unwrap();
+            let weights = read_weights(&test_config(&name, *nthreads));
+            assert_eq!(weights.len(), 1);
+            assert_eq!(weights[0].len(), 5);
+
+            let mut g = graph::Graph::new();
+            for node_id in 0..10 {
+                g.add_node(node_id);
+            }
+            g.build(vec![vec![20, 21]]);
+            run(g, test_config(&name, *nthreads)).unwrap();
+            let weights = read_weights(&test_config(&name, *nthreads));
+            assert_eq!(weights.len(), 12);
+            assert!(weights.iter().flatten().all(|x| x.is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_max_code_length_too_short() {
+        // Karate's 34 nodes need codes of at least 6 bits.
+        let config = test_config_with("deepwalk_code_length", 1, r#"{"max_code_length": 5}"#);
+        match run(karate(), config) {
+            Err(DeepwalkError::Config(_)) => (),
+            _ => panic!("expected a Config error"),
+        }
+        let config = test_config_with("deepwalk_code_length", 1, r#"{"max_code_length": 6}"#);
+        run(karate(), config).unwrap();
+    }
+
+    #[test]
+    fn test_sigmoid_mode() {
+        let g = karate();
+        let output_layer = OutputLayer::HierarchicalSoftmax(huffman_tree::HuffmanTree::new(&g, 0));
+        let modes = [
+            ("table", config::SigmoidMode::Table),
+            ("exact", config::SigmoidMode::Exact),
+        ];
+        for (name, mode) in modes.iter() {
+            let extra = format!(r#"{{"sigmoid": "{}"}}"#, name);
+            let config = test_config_with("deepwalk_sigmoid", 1, &extra);
+            let model = new_model::<f64>(&g, &output_layer, &config);
+            assert_eq!(model.sigmoid_mode(), *mode);
+        }
+    }
+
+    #[test]
+    fn test_graph_stats() {
+        let input = env::temp_dir().join("deepwalk_graph_stats.txt");
+        fs::write(&input, "1 2\n2 1\n1 2\n2 3\n3 3\n").unwrap();
+        for (duplicates, self_loops) in [("keep", "keep"), ("merge", "drop")].iter() {
+            let extra = serde_json::json!({
+                "input_file": input,
+                "duplicate_edges": duplicates,
+                "self_loops": self_loops,
+            });
+            let config = test_config_with("deepwalk_graph_stats", 1, &extra.to_string());
+            let stats = graph_stats(&config).unwrap();
+            assert_eq!((stats.duplicate_edges, stats.self_loops), (2, 1));
+        }
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn test_components() {
+        // Karate plus a pair and an isolated node.
+        let fragments = || {
+            let mut g = karate();
+            g.build(vec![vec![100, 101]]);
+            g.add_node(102);
+            g
+        };
+        for nthreads in [1, 4].iter() {
+            let name = format!("deepwalk_components_{}", nthreads);
+            let extra = r#"{"min_component_size": 2, "component_column": true}"#;
+            run(fragments(), test_config_with(&name, *nthreads, extra)).unwrap();
+            let weights = read_weights(&test_config(&name, *nthreads));
+            assert_eq!(weights.len(), 36);
+            for w in &weights {
+                let component = if w[0] >= 100.0 { 1.0 } else { 0.0 };
+                assert_eq!((w[1], w.len()), (component, 6));
+            }
+
+            let extra = r#"{"largest_component": true}"#;
+            run(fragments(), test_config_with(&name, *nthreads, extra)).unwrap();
+            let weights = read_weights(&test_config(&name, *nthreads));
+            assert_eq!(weights.len(), 34);
+            assert!(weights.iter().all(|w| w[0] < 100.0 && w.len() == 5));
+
+            // Holding out half of a ring splits the training graph, the
+            // labels are those of the input.
+            let mut ring = graph::Graph::new();
+            ring.build((0..20).map(|i| vec![i, (i + 1) % 20]).collect());
+            let extra = r#"{"component_column": true, "early_stopping": "link_auc",
+                "holdout_fraction": 0.5}"#;
+            run(ring, test_config_with(&name, *nthreads, extra)).unwrap();
+            let weights = read_weights(&test_config(&name, *nthreads));
+            assert_eq!(weights.len(), 20);
+            assert!(weights.iter().all(|w| w[1] == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_attributes() {
+        let extra = r#"{
+            "attribute_file": "karate_communities.txt",
+            "output_attributes": ["community"],
+            "component_column": true
+        }"#;
+        let config = test_config_with("deepwalk_attributes", 1, extra);
+        let g = load_graph(&config).unwrap();
+        assert_eq!(g.attributes().len(), 34);
+        run(g, config).unwrap();
+        let weights = read_weights(&test_config("deepwalk_attributes", 1));
+        for w in &weights {
+            let community = if [10, 15, 16, 19, 21].contains(&(w[0] as i32)) || w[0] > 22.0 {
+                1.0
+            } else {
+                0.0
+            };
+            assert_eq!((w[1], w[2], w.len()), (0.0, community, 7));
+        }
+
+        let extra =
+            r#"{"attribute_file": "karate_communities.txt", "output_attributes": ["club"]}"#;
+        let config = test_config_with("deepwalk_attributes", 1, extra);
+        assert!(matches!(load_graph(&config), Err(DeepwalkError::Config(_))));
+    }
+
+    #[test]
+    fn test_cbow_and_negative_sampling() {
+        for nthreads in [1, 4].iter() {
+            for (architecture, objective) in [
+                ("skip_gram", "negative_sampling"),
+                ("cbow", "hierarchical_softmax"),
+                ("cbow", "negative_sampling"),
+            ]
+            .iter()
+            {
+                let name = format!("deepwalk_{}_{}_{}", architecture, objective, nthreads);
+                let extra = format!(
+                    r#"{{"architecture": "{}", "objective": "{}", "vector_dim": 8,
+                        "walk_length": 20, "window_size": 5, "num_iterations": 10}}"#,
+                    architecture, objective
+                );
+                run(karate(), test_config_with(&name, *nthreads, &extra)).unwrap();
+                let weights = read_weights(&test_config_with(&name, *nthreads, &extra));
+                assert_eq!(weights.len(), 34);
+                assert!(weights.iter().flatten().all(|x| x.is_finite()));
+                let auc = karate_link_auc(&weights);
+                assert!(auc > 0.6, "{} {} AUC {}", architecture, objective, auc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk_modes() {
+        let modes = [
+            ("uniform", true),
+            ("restart", false),
+            ("restart", true),
+            ("app", true),
+        ];
+        for nthreads in [1, 4].iter() {
+            for (walk_mode, non_backtracking) in modes.iter() {
+                let name = format!("deepwalk_{}_{}_{}", walk_mode, non_backtracking, nthreads);
+                let extra = format!(
+                    r#"{{"walk_mode": "{}", "non_backtracking": {}, "alpha": 0.3,
+                        "vector_dim": 8, "walk_length": 20, "window_size": 5,
+                        "num_iterations": 10}}"#,
+                    walk_mode, non_backtracking
+                );
+                run(karate(), test_config_with(&name, *nthreads, &extra)).unwrap();
+                let weights = read_weights(&test_config_with(&name, *nthreads, &extra));
+                assert_eq!(weights.len(), 34);
+                assert!(weights.iter().flatten().all(|x| x.is_finite()));
+                let auc = karate_link_auc(&weights);
+                assert!(auc > 0.6, "{} AUC {}", walk_mode, auc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk_frequencies() {
+        let mut star = graph::Graph::new();
+        star.build(vec![vec![0, 1], vec![0, 2], vec![0, 3], vec![0, 4]]);
+        // Every step returns to the start.
+        let extra = r#"{"walk_mode": "restart", "alpha": 1.0}"#;
+        let frequencies = walk_frequencies(&star, &test_config_with("unused", 1, extra));
+        assert_eq!(frequencies.len(), 5);
+        assert!(frequencies.values().all(|count| *count == 5));
+        // Every walk stops after its first step, so a leaf stops at the
+        // center every time.
+        let extra = r#"{"walk_mode": "app", "alpha": 1.0}"#;
+        let frequencies = walk_frequencies(&star, &test_config_with("unused", 1, extra));
+        assert_eq!(frequencies[&0], 1 + 4 * 5);
+        assert_eq!(frequencies.values().sum::<u64>(), 5 + 5 * 5);
+
+        // A non backtracking walk goes once around a ring.
+        let mut ring = graph::Graph::new();
+        ring.build((0..5).map(|i| vec![i, (i + 1) % 5]).collect());
+        let extra = r#"{"non_backtracking": true}"#;
+        let frequencies = walk_frequencies(&ring, &test_config_with("unused", 1, extra));
+        assert_eq!(frequencies.len(), 5);
+        assert!(frequencies.values().all(|count| *count == 5));
+    }
+
+    #[test]
+    fn test_walk_contexts() {
+        let mut contexts = Vec::new();
+        let walk = [7, 1, 2, 3];
+        walk_contexts(WalkMode::App, &walk, 0, 1, &mut contexts);
+        assert_eq!(contexts, vec![1, 2, 3]);
+        walk_contexts(WalkMode::App, &walk, 2, 1, &mut contexts);
+        assert_eq!(contexts, vec![7]);
+        walk_contexts(WalkMode::Restart, &walk, 2, 2, &mut contexts);
+        assert_eq!(contexts, vec![7, 1, 3]);
+    }
+
+    #[test]
+    fn test_f32_matches_f64_on_karate() {
+        let mut aucs = Vec::new();
+        for precision in ["f32", "f64"].iter() {
+            let name = format!("deepwalk_karate_{}", precision);
+            let extra = format!(
+                r#"{{"vector_dim": 8, "walk_length": 20, "window_size": 5,
+                    "num_iterations": 20, "precision": "{}"}}"#,
+                precision
+            );
+            run(karate(), test_config_with(&name, 1, &extra)).unwrap();
+            let auc = karate_link_auc(&read_weights(&test_config_with(&name, 1, &extra)));
+            aucs.push(auc);
+        }
+        assert!(aucs[0] > 0.7, "f32 AUC {}", aucs[0]);
+        assert!(aucs[1] > 0.7, "f64 AUC {}", aucs[1]);
+        assert!((aucs[0] - aucs[1]).abs() < 0.1, "AUCs {:?}", aucs);
+    }
+
+    #[test]
+    fn test_line() {
+        let dir = env::temp_dir();
+        for nthreads in [1, 4].iter() {
+            for (order, dim) in [("first", 8), ("second", 8), ("both", 16)].iter() {
+                let name = format!("deepwalk_line_{}_{}", order, nthreads);
+                let context_file = dir.join(format!("{}_context.txt", name));
+                let mut extra = serde_json::json!({
+                    "method": "line",
+                    "line_order": order,
+                    "vector_dim": 8,
+                    "num_iterations": 100
+                });
+                if *order == "second" {
+                    extra["context_file"] = serde_json::json!(context_file);
+                }
+                let config = || test_config_with(&name, *nthreads, &extra.to_string());
+                run(karate(), config()).unwrap();
+                let weights = read_weights(&config());
+                assert_eq!(weights.len(), 34);
+                assert!(weights.iter().all(|w| w.len() == dim + 1));
+
+                // Second order proximity relates a node to the context
+                // vectors of its neighbors, not to their input vectors.
+                let auc = if *order == "second" {
+                    let vectors = |rows: Vec<Vec<f64>>| -> HashMap<i32, Vec<f64>> {
+                        rows.into_iter()
+                            .map(|w| (w[0] as i32, w[1..].to_vec()))
+                            .collect()
+                    };
+                    let input = vectors(weights);
+                    let context = vectors(
+                        fs::read_to_string(&context_file)
+                            .unwrap()
+                            .lines()
+                            .map(|l| l.split(' ').map(|x| x.parse().unwrap()).collect())
+                            .collect(),
+                    );
+                    let dot =
+                        |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+                    karate_link_auc_by(|a, b| {
+                        dot(&input[&a], &context[&b]) + dot(&input[&b], &context[&a])
+                    })
+                } else {
+                    karate_link_auc(&weights)
+                };
+                assert!(auc > 0.6, "LINE {} AUC {}", order, auc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matrix_factorization() {
+        for (method, min_auc) in [("netmf", 0.75), ("laplacian_eigenmaps", 0.75)].iter() {
+            let name = format!("deepwalk_{}", method);
+            let extra = format!(
+                r#"{{"method": "{}", "vector_dim": 8, "window_size": 5, "negative": 1}}"#,
+                method
+            );
+            run(karate(), test_config_with(&name, 1, &extra)).unwrap();
+            let weights = read_weights(&test_config_with(&name, 1, &extra));
+            assert_eq!(weights.len(), 34);
+            let auc = karate_link_auc(&weights);
+            assert!(auc > *min_auc, "{} AUC {}", method, auc);
+
+            run(karate(), test_config_with(&name, 1, &extra)).unwrap();
+            let mut again = read_weights(&test_config_with(&name, 1, &extra));
+            let mut weights = weights;
+            weights.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+            again.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+            assert_eq!(weights, again);
+
+            // The dense matrices of karate's 34 nodes exceed a limit of 30.
+            let extra = format!(r#"{{"method": "{}", "max_dense_nodes": 30}}"#, method);
+            match run(karate(), test_config_with(&name, 1, &extra)) {
+                Err(DeepwalkError::Config(_)) => (),
+                _ => panic!("expected a Config error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_early_stopping() {
+        for nthreads in [1, 4].iter() {
+            // Nothing beats the first evaluation by the tolerance.
+            let name = format!("deepwalk_early_stopping_loss_{}", nthreads);
+            let extra = r#"{"early_stopping": "loss", "patience": 2, "tolerance": 1e9,
+                "num_iterations": 50}"#;
+            run(karate(), test_config_with(&name, *nthreads, extra)).unwrap();
+            let config = test_config_with(&name, *nthreads, extra);
+            let perf = fs::read_to_string(config.perf_file()).unwrap();
+            let lines: Vec<&str> = perf.lines().collect();
+            assert_eq!(lines.len(), 4);
+            assert!(lines[0].ends_with(" validation"));
+            assert_eq!(read_weights(&config).len(), 34);
+
+            let name = format!("deepwalk_early_stopping_auc_{}", nthreads);
+            let extra = r#"{"early_stopping": "link_auc", "eval_every": 2, "vector_dim": 8,
+                "walk_length": 20, "window_size": 5, "num_iterations": 10}"#;
+            run(karate(), test_config_with(&name, *nthreads, extra)).unwrap();
+            let config = test_config_with(&name, *nthreads, extra);
+            let perf = fs::read_to_string(config.perf_file()).unwrap();
+            let validation: Vec<&str> = perf
+                .lines()
+                .skip(1)
+                .map(|l| l.split(' ').next_back().unwrap())
+                .collect();
+            assert_eq!(validation[0], "-");
+            let auc: f64 = validation[1].parse().unwrap();
+            assert!((0.0..=1.0).contains(&auc));
+            let weights = read_weights(&config);
+            assert_eq!(weights.len(), 34);
+            assert!(weights.iter().flatten().all(|x| x.is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_metrics_file() {
+        let dir = env::temp_dir();
+        for nthreads in [1, 4].iter() {
+            let name = format!("deepwalk_metrics_{}", nthreads);
+            let metrics_file = dir.join(format!("{}.jsonl", name));
+            let extra = serde_json::json!({
+                "metrics_file": metrics_file,
+                "metrics_every": 10,
+                "num_iterations": 3
+            });
+            run(
+                karate(),
+                test_config_with(&name, *nthreads, &extra.to_string()),
+            )
+            .unwrap();
+            let records: Vec<serde_json::Value> = fs::read_to_string(&metrics_file)
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect();
+            let epochs: Vec<&serde_json::Value> =
+                records.iter().filter(|r| r["kind"] == "epoch").collect();
+            assert_eq!(epochs.len(), 3);
+            assert_eq!(records.len(), 3 * 4);
+            // Step records count the walks of every thread.
+            let steps: Vec<&serde_json::Value> = records
+                .iter()
+                .filter(|r| r["kind"] == "step")
+                .map(|r| &r["walks"])
+                .collect();
+            assert_eq!(steps, [10, 20, 30, 10, 20, 30, 10, 20, 30]);
+            for epoch in epochs {
+                assert_eq!(epoch["walks"], 34);
+                // Every walk of 5 nodes with a window of 2 has 11 pairs.
+                assert_eq!(epoch["pairs"], 34 * 11);
+                assert!(epoch["loss_per_pair"].as_f64().unwrap() > 0.0);
+                assert!(epoch["norm_max"].as_f64().unwrap() > 0.0);
+                let utilization = epoch["thread_utilization"].as_f64().unwrap();
+                assert!(utilization > 0.0 && utilization <= 1.0);
+            }
+
+            let csv_file = dir.join(format!("{}.csv", name));
+            let extra = serde_json::json!({ "metrics_file": csv_file, "metrics_format": "csv" });
+            run(
+                karate(),
+                test_config_with(&name, *nthreads, &extra.to_string()),
+            )
+            .unwrap();
+            let csv = fs::read_to_string(&csv_file).unwrap();
+            let lines: Vec<&str> = csv.lines().collect();
+            assert!(lines[0].starts_with("kind,iteration,walks,pairs,loss_per_pair"));
+            assert_eq!(lines.len(), 3);
+            assert!(lines[1].starts_with("epoch,0,34,374,"));
+        }
+    }
+
+    #[test]
+    fn test_output_vector_files() {
+        let dir = env::temp_dir();
+        for nthreads in [1, 4].iter() {
+            let name = format!("deepwalk_output_vectors_{}", nthreads);
+            let path = |suffix: &str| dir.join(format!("{}_{}.txt", name, suffix));
+            let extra = serde_json::json!({
+                "objective": "negative_sampling",
+                "context_file": path("context"),
+                "combined_file": path("combined"),
+                "combine": "concat"
+            });
+            let config = test_config_with(&name, *nthreads, &extra.to_string());
+            run(karate(), config).unwrap();
+            let input = read_weights(&test_config_with(&name, *nthreads, &extra.to_string()));
+            let vectors = |suffix: &str| -> HashMap<i32, Vec<f64>> {
+                fs::read_to_string(path(suffix))
+                    .unwrap()
+                    .lines()
+                    .map(|l| {
+                        let v: Vec<f64> = l.split(' ').map(|x| x.parse().unwrap()).collect();
+                        (v[0] as i32, v[1..].to_vec())
+                    })
+                    .collect()
+            };
+            let (context, combined) = (vectors("context"), vectors("combined"));
+            assert_eq!(context.len(), 34);
+            assert_eq!(combined.len(), 34);
+            for row in &input {
+                let id = row[0] as i32;
+                let expected: Vec<f64> = row[1..].iter().chain(&context[&id]).cloned().collect();
+                assert_eq!(combined[&id], expected);
+            }
+
+            let extra = serde_json::json!({ "inner_node_file": path("inner") });
+            run(
+                karate(),
+                test_config_with(&name, *nthreads, &extra.to_string()),
+            )
+            .unwrap();
+            let inner = vectors("inner");
+            // A binary tree over 34 leaves has 33 inner nodes.
+            assert_eq!(inner.len(), 33);
+            assert!((0..33).all(|i| inner[&i].len() == 4));
+        }
     }
-    model.write_weight_mat(&config.weight_file(), graph);
 }
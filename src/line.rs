@@ -0,0 +1,236 @@
+//! LINE (Tang et al. 2015), first and second order proximity trained on
+//! edges sampled directly from the graph, with negative sampling.
+//!
+//! The first order model predicts a neighbor from its input vector, the
+//! second order model from its context (output) vector like skip-gram.
+
+use crate::alias::AliasTable;
+use crate::config::{Combine, Config, LineOrder, Objective};
+use crate::embeddings;
+use crate::error::DeepwalkError;
+use crate::float::Float;
+use crate::graph::Graph;
+use crate::logging;
+use crate::model_concurrent::{ConcurrentModel, StepBuffers};
+use crate::negative_sampling::NegativeSampler;
+use crate::output_layer::OutputLayer;
+use crossbeam::sync::WaitGroup;
+use log::info;
+use nalgebra::DMatrix;
+use rand::{thread_rng, Rng};
+use std::cmp;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// Samples directed edges `(source_idx, target_idx)` proportionally to
+/// their weight.  Every undirected edge is sampled in both directions.
+#[derive(Debug)]
+pub struct EdgeSampler {
+    edges: Vec<(usize, usize)>,
+    table: AliasTable,
+}
+
+impl EdgeSampler {
+    pub fn from_graph(graph: &Graph) -> EdgeSampler {
+        let (mut edges, mut weights) = (Vec::new(), Vec::new());
+        for (source, node_id) in graph.node_ids_by_idx().iter().enumerate() {
+            let node = graph.get_node(node_id).unwrap();
+            for (i, neighbor) in node.get_neighbors().iter().enumerate() {
+                edges.push((source, *graph.get_node_idx(neighbor).unwrap()));
+                weights.push(node.get_edge_weights().map_or(1.0, |w| w[i] as f64));
+            }
+        }
+        let table = AliasTable::new(&weights);
+        EdgeSampler { edges, table }
+    }
+
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> (usize, usize) {
+        self.edges[self.table.sample(rng)]
+    }
+}
+
+/// Trains the LINE orders requested in `config` and writes the weight file,
+/// each vector after its node's `labels`.  Each iteration samples as many
+/// edges as the graph has.
+pub fn run<T: Float>(graph: Graph, labels: &[String], config: Config) -> Result<(), DeepwalkError> {
+    let graph = Arc::new(graph);
+    let config = Arc::new(config);
+    let edges = Arc::new(EdgeSampler::from_graph(&graph));
+    let output_layer = Arc::new(OutputLayer::NegativeSampling {
+        sampler: NegativeSampler::from_graph(&graph),
+        negative: config.negative(),
+        num_nodes: graph.num_nodes(),
+    });
+
+    let mut f = File::create(config.perf_file())?;
+    writeln!(f, "order iteration learning_rate error time")?;
+    let orders = match config.line_order() {
+        LineOrder::Both => vec![LineOrder::First, LineOrder::Second],
+        order => vec![order],
+    };
+    let mut models = Vec::new();
+    for order in orders {
+        let model = train_order::<T>(order, &graph, &edges, &output_layer, &config, &mut f)?;
+        models.push(model);
+    }
+
+    if let [model] = models.as_slice() {
+        let input = model.input_matrix();
+        embeddings::write_vectors(&config.weight_file(), labels, &input)?;
+        if config.exports_output_vectors() {
+            embeddings::write_output_vectors(
+                &input,
+                &model.output_matrix(),
+                &output_layer,
+                labels,
+                &config,
+            )?;
+        }
+    } else {
+        let mut first = models[0].input_matrix();
+        let mut second = models[1].input_matrix();
+        normalize_columns(&mut first);
+        normalize_columns(&mut second);
+        let weights = embeddings::combine(&first, &second, Combine::Concat);
+        embeddings::write_vectors(&config.weight_file(), labels, &weights)?;
+    }
+    Ok(())
+}
+
+fn train_order<T: Float>(
+    order: LineOrder,
+    graph: &Arc<Graph>,
+    edges: &Arc<EdgeSampler>,
+    output_layer: &Arc<OutputLayer>,
+    config: &Arc<Config>,
+    perf: &mut File,
+) -> Result<Arc<ConcurrentModel<T>>, DeepwalkError> {
+    // The first order model only uses input vectors.
+    let num_outputs = match order {
+        LineOrder::First => 0,
+        _ => graph.num_nodes(),
+    };
+    let mut model = ConcurrentModel::<T>::new(graph.num_nodes(), num_outputs, config.vector_dim());
+    model.set_sigmoid_mode(config.sigmoid());
+    model.set_objective(Objective::NegativeSampling);
+    let model = Arc::new(model);
+
+    let nthreads = cmp::max(config.nthreads(), 1);
+    let mut lr = config.learning_rate();
+    let start_lr = lr;
+    let now = Instant::now();
+    let order_number = if order == LineOrder::First { 1 } else { 2 };
+    let total_samples = (config.num_iterations() * edges.len()) as u64;
+    let bar = logging::progress_bar(total_samples, &format!("order {}", order_number), "edges");
+    for iter in 0..config.num_iterations() {
+        let error = Arc::new(Mutex::new(0.0));
+        let wg_iter = WaitGroup::new();
+        for t in 0..nthreads {
+            let samples = edges.len() / nthreads + usize::from(t < edges.len() % nthreads);
+            let wg_iter = wg_iter.clone();
+            let model = Arc::clone(&model);
+            let error = Arc::clone(&error);
+            let edges = Arc::clone(edges);
+            let output_layer = Arc::clone(output_layer);
+            let learning_rate = lr;
+            let vector_dim = config.vector_dim();
+            let bar = bar.clone();
+            thread::spawn(move || {
+                let mut rng = thread_rng();
+                let mut buffers = StepBuffers::new(vector_dim);
+                let (mut points, mut codes) = (Vec::new(), Vec::new());
+                let mut thread_error = 0.0;
+                for sample in 0..samples {
+                    if sample % 1024 == 1023 {
+                        bar.inc(1024);
+                    }
+                    let (source, target) = edges.sample(&mut rng);
+                    let (p, c) = output_layer.outputs(target, &mut rng, &mut points, &mut codes);
+                    thread_error += match order {
+                        LineOrder::First => {
+                            model.step_first_order(source, p, c, learning_rate, &mut buffers)
+                        }
+                        _ => model.step(source, p, c, learning_rate, &mut buffers),
+                    };
+                }
+                bar.inc(samples as u64 % 1024);
+                *error.lock().unwrap() += thread_error;
+                drop(wg_iter);
+            });
+        }
+        wg_iter.wait();
+        let err = *error.lock().unwrap() / (cmp::max(edges.len(), 1) as f64);
+        info!(
+            "Order {} iteration {}: learning rate {}, error {}",
+            order_number, iter, lr, err
+        );
+        writeln!(
+            perf,
+            "{} {} {} {} {}",
+            order_number,
+            iter,
+            lr,
+            err,
+            now.elapsed().as_secs()
+        )?;
+
+        lr -= start_lr / (config.num_iterations() as f64);
+    }
+    bar.finish_and_clear();
+    Ok(model)
+}
+
+/// Scales every column to unit length, zero columns are left alone.
+fn normalize_columns<T: Float>(mat: &mut DMatrix<T>) {
+    for mut column in mat.column_iter_mut() {
+        let norm = column.norm();
+        if norm > T::zero() {
+            column /= norm;
+        }
+    }
+}
+
+#[cfg(test)]
+mod line_tests {
+    use super::*;
+
+    #[test]
+    fn test_edge_sampler() {
+        let mut g = Graph::new();
+        g.build(vec![vec![1, 2], vec![2, 3]]);
+        let edges = EdgeSampler::from_graph(&g);
+        assert_eq!(edges.len(), 4);
+
+        let mut rng = thread_rng();
+        let mut counts = [[0; 3]; 3];
+        for _ in 0..4000 {
+            let (source, target) = edges.sample(&mut rng);
+            counts[source][target] += 1;
+        }
+        let (i1, i2, i3) = (
+            *g.get_node_idx(&1).unwrap(),
+            *g.get_node_idx(&2).unwrap(),
+            *g.get_node_idx(&3).unwrap(),
+        );
+        assert_eq!(counts[i1][i3] + counts[i3][i1], 0);
+        assert!(counts[i2][i1] > 800 && counts[i1][i2] > 800);
+    }
+
+    #[test]
+    fn test_normalize_columns() {
+        let mut mat = DMatrix::from_row_slice(2, 2, &[3.0, 0.0, 4.0, 0.0]);
+        normalize_columns(&mut mat);
+        assert_eq!(mat, DMatrix::from_row_slice(2, 2, &[0.6, 0.0, 0.8, 0.0]));
+    }
+}
@@ -0,0 +1,524 @@
+//! Streaming edge list and adjacency list loaders, and `load_graph` which
+//! picks the loader for the input format.
+//!
+//! Lines are read through a reused buffer into a `GraphBuilder`, which
+//! holds 8 bytes per edge until the graph is built, rather than a multiple
+//! of the file size.
+//!
+//! Blank and comment lines are skipped, lines that can't be parsed are
+//! handled by the `MalformedLines` policy.
+
+use crate::config::{Config, InputFormat, MalformedLines};
+use crate::error::DeepwalkError;
+use crate::graph::{EdgePolicy, Graph, GraphBuilder, NodeID};
+use crate::graphml;
+use crate::input;
+use crate::logging;
+use crate::matrix_market;
+use indicatif::ProgressBar;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Bytes read between progress bar updates.
+const PROGRESS_BYTES: u64 = 1 << 16;
+
+/// Malformed lines kept in a `LoadReport` for the warnings.
+const MAX_EXAMPLES: usize = 10;
+
+/// How the lines of an edge list are split into edges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeListFormat {
+    /// `None` splits on any run of whitespace.
+    pub delimiter: Option<char>,
+    /// Lines starting with this prefix are skipped, none if empty.
+    pub comment: String,
+    /// Skip the first line.
+    pub header: bool,
+    /// The zero based source and target columns, others are ignored.
+    pub columns: [usize; 2],
+    pub malformed_lines: MalformedLines,
+}
+
+impl Default for EdgeListFormat {
+    fn default() -> EdgeListFormat {
+        EdgeListFormat {
+            delimiter: None,
+            comment: "#".to_string(),
+            header: false,
+            columns: [0, 1],
+            malformed_lines: MalformedLines::Strict,
+        }
+    }
+}
+
+impl EdgeListFormat {
+    pub fn from_config(config: &Config) -> EdgeListFormat {
+        EdgeListFormat {
+            delimiter: config.delimiter(),
+            comment: config.comment().to_string(),
+            header: config.header(),
+            columns: config.columns(),
+            malformed_lines: config.malformed_lines(),
+        }
+    }
+
+    /// Every field of `line`, trimmed with a delimiter.
+    pub fn fields<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match self.delimiter {
+            Some(d) => line.trim().split(d).map(str::trim).collect(),
+            None => line.split_whitespace().collect(),
+        }
+    }
+
+    fn num_fields(&self, line: &str) -> usize {
+        match self.delimiter {
+            Some(d) => line.split(d).count(),
+            None => line.split_whitespace().count(),
+        }
+    }
+
+    /// Whether `line` is blank or a comment.
+    pub fn is_skipped(&self, line: &str) -> bool {
+        let trimmed = line.trim();
+        trimmed.is_empty() || (!self.comment.is_empty() && trimmed.starts_with(&self.comment))
+    }
+
+    /// The edge in the selected columns of `line`.
+    fn parse_edge(&self, line: &str) -> Result<(NodeID, NodeID), String> {
+        let line = line.trim();
+        let [source, target] = self.columns;
+        let mut tokens = [None; 2];
+        let mut select = |(column, token)| {
+            if column == source {
+                tokens[0] = Some(token);
+            }
+            if column == target {
+                tokens[1] = Some(token);
+            }
+        };
+        let columns = source.max(target) + 1;
+        match self.delimiter {
+            Some(d) => line
+                .split(d)
+                .map(str::trim)
+                .take(columns)
+                .enumerate()
+                .for_each(&mut select),
+            None => line
+                .split_whitespace()
+                .take(columns)
+                .enumerate()
+                .for_each(&mut select),
+        }
+        let mut edge = [0; 2];
+        for ((node, token), column) in edge.iter_mut().zip(tokens).zip(self.columns) {
+            let token = token.ok_or_else(|| {
+                format!(
+                    "expected at least {} columns, found {}",
+                    column + 1,
+                    self.num_fields(line)
+                )
+            })?;
+            *node = parse_node(token)?;
+        }
+        Ok((edge[0], edge[1]))
+    }
+
+    /// Fills `nodes` with every node id on `line`, a node followed by its
+    /// neighbors.
+    fn parse_adjacency(&self, line: &str, nodes: &mut Vec<NodeID>) -> Result<(), String> {
+        nodes.clear();
+        let mut push = |token: &str| -> Result<(), String> {
+            nodes.push(parse_node(token)?);
+            Ok(())
+        };
+        match self.delimiter {
+            Some(d) => line
+                .split(d)
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .try_for_each(&mut push),
+            None => line.split_whitespace().try_for_each(&mut push),
+        }
+    }
+}
+
+/// What a load read.  Line numbers are one based.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LoadReport {
+    pub lines: usize,
+    pub edges: usize,
+    /// Blank, comment and header lines.
+    pub skipped: usize,
+    pub malformed: usize,
+    /// Repeated edges and self loops, counted before the `EdgePolicy`
+    /// applied to them.
+    pub duplicates: usize,
+    pub self_loops: usize,
+    /// Edges skipped as the second listing of an edge under its other
+    /// node, see `Mirrors`.
+    pub mirrored: usize,
+    /// Line numbers and reasons of the first malformed lines.
+    pub examples: Vec<(usize, String)>,
+}
+
+impl LoadReport {
+    fn log(&self, policy: MalformedLines) {
+        info!(
+            "Loaded {} edges with {} duplicates and {} self loops, \
+             skipped {} blank, comment or header lines",
+            self.edges, self.duplicates, self.self_loops, self.skipped
+        );
+        if self.mirrored > 0 {
+            info!(
+                "Skipped {} edges listed a second time under their other node",
+                self.mirrored
+            );
+        }
+        if self.malformed == 0 {
+            return;
+        }
+        if policy == MalformedLines::Warn {
+            for (line, message) in &self.examples {
+                warn!("Skipped line {}: {}", line, message);
+            }
+            warn!("Skipped {} malformed lines", self.malformed);
+        } else {
+            info!("Skipped {} malformed lines", self.malformed);
+        }
+    }
+}
+
+/// Matches the two listings of an undirected edge in formats that may
+/// list it under both of its nodes, like adjacency lists.  An edge `(a, b)`
+/// pairs with one earlier unpaired `(b, a)`, so an edge listed under both
+/// nodes is added once while one listed under a single node, or repeated
+/// under the same node, is still added every time.
+#[derive(Debug, Default)]
+pub struct Mirrors {
+    unpaired: HashMap<(NodeID, NodeID), usize>,
+}
+
+impl Mirrors {
+    /// Whether `(a, b)` should be added, false if it is the mirror of an
+    /// earlier edge.  Self loops are always added.
+    pub fn is_new(&mut self, a: NodeID, b: NodeID) -> bool {
+        if a == b {
+            return true;
+        }
+        if let Some(count) = self.unpaired.get_mut(&(b, a)) {
+            *count -= 1;
+            if *count == 0 {
+                self.unpaired.remove(&(b, a));
+            }
+            return false;
+        }
+        *self.unpaired.entry((a, b)).or_insert(0) += 1;
+        true
+    }
+}
+
+/// The format of `path` by its extension, ignoring a `.gz` or `.zst`
+/// compression extension.  Everything else, and stdin, is an edge list.
+pub fn detect_format(path: &str) -> InputFormat {
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("");
+    let name = name
+        .strip_suffix(".gz")
+        .or_else(|| name.strip_suffix(".zst"))
+        .unwrap_or(name);
+    match Path::new(name).extension().and_then(|e| e.to_str()) {
+        Some("adj") | Some("adjlist") => InputFormat::AdjacencyList,
+        Some("mtx") => InputFormat::MatrixMarket,
+        Some("graphml") => InputFormat::Graphml,
+        Some("gexf") => InputFormat::Gexf,
+        _ => InputFormat::EdgeList,
+    }
+}
+
+/// Loads `filename` in `input_format`, or the format detected from its
+/// extension for `auto`, into `graph`, applies `policy` and logs the
+/// report.  `format` applies to the line based formats.
+pub fn load_graph(
+    graph: &mut Graph,
+    filename: &str,
+    input_format: InputFormat,
+    format: &EdgeListFormat,
+    policy: EdgePolicy,
+) -> Result<LoadReport, DeepwalkError> {
+    let input_format = match input_format {
+        InputFormat::Auto => detect_format(filename),
+        f => f,
+    };
+    debug!("Reading {} as {:?}", filename, input_format);
+    let mut report = match input_format {
+        InputFormat::Auto | InputFormat::EdgeList => load_edge_list(graph, filename, format)?,
+        InputFormat::AdjacencyList => load_adjacency_list(graph, filename, format)?,
+        InputFormat::MatrixMarket => matrix_market::load(graph, filename)?,
+        InputFormat::Graphml | InputFormat::Gexf => graphml::load(graph, filename)?,
+    };
+    let counts = graph.apply_edge_policy(policy);
+    report.duplicates = counts.duplicates;
+    report.self_loops = counts.self_loops;
+    report.log(format.malformed_lines);
+    Ok(report)
+}
+
+/// Loads the edge list `filename`, which may be compressed or `-` for
+/// stdin, into `graph`.
+pub fn load_edge_list(
+    graph: &mut Graph,
+    filename: &str,
+    format: &EdgeListFormat,
+) -> Result<LoadReport, DeepwalkError> {
+    let bar = if input::is_plain_file(filename)? {
+        logging::progress_bar(File::open(filename)?.metadata()?.len(), "loading", "bytes")
+    } else {
+        logging::spinner("loading", "bytes")
+    };
+    let mut reader = input::open(filename)?;
+    let mut builder = GraphBuilder::new();
+    let result = read_edges(&mut reader, format, &bar, |a, b| builder.add_edge(a, b));
+    bar.finish_and_clear();
+    builder.build_into(graph);
+    result
+}
+
+/// Loads an adjacency list, each line a node followed by its neighbors.
+/// An edge may be listed under one or both of its nodes, the second
+/// listing is paired with the first by `Mirrors` and skipped.  A node
+/// without neighbors is added isolated.
+pub fn load_adjacency_list(
+    graph: &mut Graph,
+    filename: &str,
+    format: &EdgeListFormat,
+) -> Result<LoadReport, DeepwalkError> {
+    let bar = logging::spinner("loading", "bytes");
+    let mut reader = input::open(filename)?;
+    let mut nodes = Vec::new();
+    let mut builder = GraphBuilder::new();
+    let (mut mirrors, mut mirrored) = (Mirrors::default(), 0);
+    let result = read_lines(&mut reader, format, &bar, |line| {
+        format.parse_adjacency(line, &mut nodes)?;
+        let (node, neighbors) = nodes.split_first().ok_or("expected a node id")?;
+        builder.add_node(*node);
+        let mut added = 0;
+        for neighbor in neighbors {
+            if mirrors.is_new(*node, *neighbor) {
+                builder.add_edge(*node, *neighbor);
+                added += 1;
+            } else {
+                mirrored += 1;
+            }
+        }
+        Ok(added)
+    });
+    bar.finish_and_clear();
+    builder.build_into(graph);
+    result.map(|report| LoadReport { mirrored, ..report })
+}
+
+/// Reads lines until the input ends and calls `add` for every edge.
+fn read_edges<R, F>(
+    reader: &mut R,
+    format: &EdgeListFormat,
+    bar: &ProgressBar,
+    mut add: F,
+) -> Result<LoadReport, DeepwalkError>
+where
+    R: BufRead,
+    F: FnMut(NodeID, NodeID),
+{
+    read_lines(reader, format, bar, |line| {
+        let (a, b) = format.parse_edge(line)?;
+        add(a, b);
+        Ok(1)
+    })
+}
+
+/// Reads lines like `read_edges` and passes those that are not blank,
+/// comments or the header to `parse`, which returns the number of edges
+/// added or why the line is malformed.
+fn read_lines<R, F>(
+    reader: &mut R,
+    format: &EdgeListFormat,
+    bar: &ProgressBar,
+    mut parse: F,
+) -> Result<LoadReport, DeepwalkError>
+where
+    R: BufRead,
+    F: FnMut(&str) -> Result<usize, String>,
+{
+    let mut report = LoadReport::default();
+    let mut line = String::new();
+    let mut unreported = 0;
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line)? as u64;
+        if n == 0 {
+            break;
+        }
+        unreported += n;
+        report.lines += 1;
+        if unreported >= PROGRESS_BYTES {
+            bar.inc(unreported);
+            unreported = 0;
+        }
+        if (format.header && report.lines == 1) || format.is_skipped(&line) {
+            report.skipped += 1;
+            continue;
+        }
+        match parse(&line) {
+            Ok(edges) => report.edges += edges,
+            Err(message) => {
+                if format.malformed_lines == MalformedLines::Strict {
+                    return Err(DeepwalkError::Parse {
+                        line: report.lines,
+                        message,
+                    });
+                }
+                report.malformed += 1;
+                if report.examples.len() < MAX_EXAMPLES {
+                    report.examples.push((report.lines, message));
+                }
+            }
+        }
+    }
+    bar.inc(unreported);
+    Ok(report)
+}
+
+fn parse_node(token: &str) -> Result<NodeID, String> {
+    token
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid node id", token))
+}
+
+pub fn parse_token<T: FromStr>(token: &str, line: usize) -> Result<T, DeepwalkError> {
+    token.parse::<T>().map_err(|_| DeepwalkError::Parse {
+        line,
+        message: format!("{:?} is not a valid number", token),
+    })
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use super::*;
+    use std::fs;
+
+    fn read(
+        contents: &str,
+        format: &EdgeListFormat,
+    ) -> Result<(Vec<(NodeID, NodeID)>, LoadReport), DeepwalkError> {
+        let mut edges = Vec::new();
+        let bar = ProgressBar::hidden();
+        let report = read_edges(&mut contents.as_bytes(), format, &bar, |a, b| {
+            edges.push((a, b))
+        })?;
+        Ok((edges, report))
+    }
+
+    #[test]
+    fn test_read_edges() {
+        let format = EdgeListFormat::default();
+        let (edges, report) = read("# comment\n1 2\n\n2\t3\r\n3 1 0.5", &format).unwrap();
+        assert_eq!(edges, vec![(1, 2), (2, 3), (3, 1)]);
+        assert_eq!(report.lines, 5);
+        assert_eq!(report.skipped, 2);
+
+        match read("1 2\n2\n", &format) {
+            Err(DeepwalkError::Parse { line, .. }) => assert_eq!(line, 2),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(read("1 x\n", &format).is_err());
+    }
+
+    #[test]
+    fn test_csv_format() {
+        let format = EdgeListFormat {
+            delimiter: Some(','),
+            comment: "%".to_string(),
+            header: true,
+            columns: [2, 0],
+            malformed_lines: MalformedLines::Skip,
+        };
+        let contents = "a,weight,b\n1, 0.5, 2\n% 2,1,3\n3,1\n4,x,5\nx,1,6\n";
+        let (edges, report) = read(contents, &format).unwrap();
+        assert_eq!(edges, vec![(2, 1), (5, 4)]);
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.malformed, 2);
+        assert_eq!(report.examples[0].0, 4);
+        assert_eq!(report.examples[1].0, 6);
+    }
+
+    #[test]
+    fn test_adjacency_list() {
+        let path = std::env::temp_dir().join("deepwalk_loader.adjlist");
+        fs::write(&path, "# node neighbors\n1 2 3\n2 3\n4\n3 1 2 1\n").unwrap();
+        let path = path.to_str().unwrap();
+        let format = EdgeListFormat::default();
+        let mut g = Graph::new();
+        let policy = EdgePolicy::default();
+        let report = load_graph(&mut g, path, InputFormat::Auto, &format, policy).unwrap();
+        // 3 lists its edges to 1 and 2 again, and a second edge to 1.
+        assert_eq!(report.edges, 4);
+        assert_eq!(report.mirrored, 2);
+        assert_eq!(g.node_ids_by_idx(), vec![1, 2, 3, 4]);
+        assert_eq!(g.get_node(&3).unwrap().get_neighbors(), &[1, 2, 1]);
+        assert!(g.get_node(&4).unwrap().get_neighbors().is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format("graph.txt"), InputFormat::EdgeList);
+        assert_eq!(detect_format("-"), InputFormat::EdgeList);
+        assert_eq!(detect_format("data/graph.adj"), InputFormat::AdjacencyList);
+        assert_eq!(detect_format("graph.mtx.gz"), InputFormat::MatrixMarket);
+        assert_eq!(detect_format("graph.graphml.zst"), InputFormat::Graphml);
+        assert_eq!(detect_format("graph.gexf"), InputFormat::Gexf);
+        assert_eq!(detect_format("graph.gz"), InputFormat::EdgeList);
+    }
+
+    #[test]
+    fn test_edge_list() {
+        let path = std::env::temp_dir().join("deepwalk_loader_edges.txt");
+        let mut contents = "src dst\n".to_string();
+        contents.push_str(&"1 2\n".repeat(100));
+        contents.push_str("3 x\n4 5\n3 3\n1 4\n");
+        fs::write(&path, contents).unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut format = EdgeListFormat {
+            header: true,
+            ..EdgeListFormat::default()
+        };
+        let mut g = Graph::new();
+        match load_edge_list(&mut g, path, &format) {
+            Err(DeepwalkError::Parse { line, .. }) => assert_eq!(line, 102),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        format.malformed_lines = MalformedLines::Warn;
+        let mut g = Graph::new();
+        let report = load_edge_list(&mut g, path, &format).unwrap();
+        assert_eq!(report.lines, 105);
+        assert_eq!(report.edges, 103);
+        assert_eq!(
+            report.examples,
+            vec![(102, "\"x\" is not a valid node id".to_string())]
+        );
+        // Nodes are indexed and neighbors listed in file order.
+        assert_eq!(g.node_ids_by_idx(), vec![1, 2, 4, 5, 3]);
+        assert_eq!(g.get_node(&1).unwrap().get_neighbors().len(), 101);
+        assert_eq!(g.get_node(&4).unwrap().get_neighbors(), &[5, 1]);
+        assert_eq!(g.get_node(&3).unwrap().get_neighbors(), &[3]);
+        fs::remove_file(path).unwrap();
+    }
+}
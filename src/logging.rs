@@ -0,0 +1,111 @@
+//! Leveled logging and progress bars, both on stderr so stdout stays free
+//! for machine readable output.
+//!
+//! Log lines are printed through the shared `MultiProgress` so they don't
+//! tear a progress bar that is being drawn.  Until `init` is called, e.g.
+//! when the library is used directly, nothing is logged or drawn.
+
+use crate::config::LogLevel;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::OnceLock;
+
+const SPINNER_TEMPLATE: &str = "{spinner} {msg} {human_pos} {prefix} ({per_sec})";
+const TEMPLATE: &str = "{msg} [{bar:30}] {human_pos}/{human_len} {prefix} ({per_sec}, ETA {eta})";
+
+fn multi() -> &'static MultiProgress {
+    static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::hidden()))
+}
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            multi().suspend(|| eprintln!("[{}] {}", record.level(), record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Sets the log level and shows progress bars if `progress` is set, the
+/// level is not quiet and stderr is a terminal.
+pub fn init(level: LogLevel, progress: bool) {
+    static LOGGER: StderrLogger = StderrLogger;
+    // Only the first call installs the logger, later calls change the level.
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level_filter(level));
+    multi().set_draw_target(draw_target(level, progress));
+}
+
+fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Quiet => LevelFilter::Off,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+    }
+}
+
+/// Stderr, which is hidden unless it is a terminal, or hidden if progress
+/// bars are off or the level is quiet.
+fn draw_target(level: LogLevel, progress: bool) -> ProgressDrawTarget {
+    if progress && level != LogLevel::Quiet {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    }
+}
+
+/// A progress bar over `len` units named `unit`, e.g. walks, with
+/// throughput and ETA.  Call `finish_and_clear` when done.
+pub fn progress_bar(len: u64, message: &str, unit: &str) -> ProgressBar {
+    let bar = multi().add(ProgressBar::new(len));
+    bar.set_style(
+        ProgressStyle::with_template(TEMPLATE)
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar.set_message(message.to_string());
+    bar.set_prefix(unit.to_string());
+    bar
+}
+
+/// A progress indicator for an unknown number of units, e.g. bytes read
+/// from stdin.
+pub fn spinner(message: &str, unit: &str) -> ProgressBar {
+    let bar = multi().add(ProgressBar::new_spinner());
+    bar.set_style(ProgressStyle::with_template(SPINNER_TEMPLATE).unwrap());
+    bar.set_message(message.to_string());
+    bar.set_prefix(unit.to_string());
+    bar
+}
+
+#[cfg(test)]
+mod logging_tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_level_filter() {
+        assert!(Level::Error > level_filter(LogLevel::Quiet));
+        assert!(Level::Info <= level_filter(LogLevel::Info));
+        assert!(Level::Debug > level_filter(LogLevel::Info));
+        assert!(Level::Debug <= level_filter(LogLevel::Debug));
+    }
+
+    #[test]
+    fn test_draw_target() {
+        assert!(draw_target(LogLevel::Info, false).is_hidden());
+        assert!(draw_target(LogLevel::Quiet, true).is_hidden());
+        assert!(draw_target(LogLevel::Debug, false).is_hidden());
+        // Nothing is drawn until `init` is called.
+        assert!(progress_bar(10, "test", "walks").is_hidden());
+        assert!(spinner("test", "bytes").is_hidden());
+    }
+}
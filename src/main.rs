@@ -1,4 +1,5 @@
 use std::env;
+use std::process;
 
 /// Runs [deepwalk](https://arxiv.org/abs/1403.6652) on a whitespace separated edge list.
 /// 
@@ -10,7 +11,11 @@ use std::env;
 /// 
 /// ```
 /// deepwalk config.json
+/// deepwalk stats config.json
 /// ```
 fn main() {
-    deepwalk::deepwalk(env::args().collect())
+    if let Err(e) = deepwalk::deepwalk(env::args().collect()) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,204 @@
+//! Matrix Market coordinate files (`.mtx`), as distributed by SuiteSparse
+//! and SNAP.  Row and column indices are the node ids, so a matrix of size
+//! `n` has the nodes `1..=n` in that order, including isolated ones.
+//! Values are ignored.
+//!
+//! A `symmetric`, `skew-symmetric` or `hermitian` matrix stores each edge
+//! once and every entry adds one edge.  A `general` matrix of an undirected
+//! graph stores both `(i, j)` and `(j, i)`, so the second of the two is
+//! paired with the first by `Mirrors` and skipped.
+
+use crate::error::DeepwalkError;
+use crate::graph::{Graph, NodeID};
+use crate::input;
+use crate::loader::{parse_token, LoadReport, Mirrors};
+use crate::logging;
+use std::io::BufRead;
+
+const BANNER: &str = "%%matrixmarket";
+
+/// Loads the square coordinate matrix `filename` into `graph`.
+pub fn load(graph: &mut Graph, filename: &str) -> Result<LoadReport, DeepwalkError> {
+    read(graph, input::open(filename)?)
+}
+
+/// Reads a square coordinate matrix from `input` into `graph`.
+pub fn read<R: BufRead>(graph: &mut Graph, input: R) -> Result<LoadReport, DeepwalkError> {
+    let mut lines = input.lines();
+    let mut report = LoadReport::default();
+    let mut next_line = |report: &mut LoadReport| -> Result<Option<String>, DeepwalkError> {
+        match lines.next() {
+            Some(line) => {
+                report.lines += 1;
+                Ok(Some(line?))
+            }
+            None => Ok(None),
+        }
+    };
+    let parse_error = |line, message: &str| DeepwalkError::Parse {
+        line,
+        message: message.to_string(),
+    };
+
+    let banner = next_line(&mut report)?.unwrap_or_default().to_lowercase();
+    let banner: Vec<&str> = banner.split_whitespace().collect();
+    if banner.len() < 3 || banner[0] != BANNER || banner[1] != "matrix" {
+        return Err(parse_error(1, "expected a %%MatrixMarket matrix header"));
+    }
+    if banner[2] != "coordinate" {
+        return Err(parse_error(1, "only coordinate matrices are graphs"));
+    }
+    let general = match banner.get(4) {
+        None | Some(&"general") => true,
+        Some(&"symmetric") | Some(&"skew-symmetric") | Some(&"hermitian") => false,
+        Some(symmetry) => return Err(parse_error(1, &format!("unknown symmetry {:?}", symmetry))),
+    };
+    report.skipped += 1;
+
+    let size = loop {
+        match next_line(&mut report)? {
+            Some(line) if is_skipped(&line) => report.skipped += 1,
+            Some(line) => break line,
+            None => return Err(parse_error(report.lines, "missing the size line")),
+        }
+    };
+    let size: Vec<usize> = size
+        .split_whitespace()
+        .map(|token| parse_token(token, report.lines))
+        .collect::<Result<_, _>>()?;
+    let (n, entries) = match size.as_slice() {
+        [rows, cols, entries] if rows == cols && *rows <= NodeID::MAX as usize => (*rows, *entries),
+        [rows, cols, _] => {
+            return Err(parse_error(
+                report.lines,
+                &format!("only square matrices are graphs, found {}x{}", rows, cols),
+            ))
+        }
+        _ => {
+            return Err(parse_error(
+                report.lines,
+                "expected rows, columns and entries",
+            ))
+        }
+    };
+    for node in 1..=n {
+        graph.add_node(node as NodeID);
+    }
+
+    let bar = logging::progress_bar(entries as u64, "loading", "entries");
+    let mut mirrors = Mirrors::default();
+    while let Some(line) = next_line(&mut report)? {
+        if is_skipped(&line) {
+            report.skipped += 1;
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let mut node = || -> Result<NodeID, DeepwalkError> {
+            let token = tokens
+                .next()
+                .ok_or_else(|| parse_error(report.lines, "expected a row and a column"))?;
+            let idx: usize = parse_token(token, report.lines)?;
+            if idx == 0 || idx > n {
+                return Err(parse_error(
+                    report.lines,
+                    &format!("index {} is outside 1..={}", idx, n),
+                ));
+            }
+            Ok(idx as NodeID)
+        };
+        let (row, col) = (node()?, node()?);
+        if general && !mirrors.is_new(row, col) {
+            report.mirrored += 1;
+        } else {
+            graph.add_edge(row, col);
+            report.edges += 1;
+        }
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+
+    if report.edges + report.mirrored != entries {
+        return Err(DeepwalkError::Format(format!(
+            "the size line promises {} entries, found {}",
+            entries,
+            report.edges + report.mirrored
+        )));
+    }
+    Ok(report)
+}
+
+fn is_skipped(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('%')
+}
+
+#[cfg(test)]
+mod matrix_market_tests {
+    use super::*;
+
+    fn read_str(contents: &str) -> Result<(Graph, LoadReport), DeepwalkError> {
+        let mut g = Graph::new();
+        read(&mut g, contents.as_bytes()).map(|r| (g, r))
+    }
+
+    #[test]
+    fn test_load() {
+        let contents = "%%MatrixMarket matrix coordinate pattern symmetric\n\
+                        % a comment\n\
+                        4 4 3\n\
+                        2 1\n\
+                        3 1\n\
+                        3 3 1.5\n";
+        let (g, report) = read_str(contents).unwrap();
+        assert_eq!(report.edges, 3);
+        assert_eq!(g.node_ids_by_idx(), vec![1, 2, 3, 4]);
+        assert_eq!(g.get_node(&1).unwrap().get_neighbors(), &[2, 3]);
+        assert_eq!(g.get_node(&3).unwrap().get_neighbors(), &[1, 3]);
+        assert!(g.get_node(&4).unwrap().get_neighbors().is_empty());
+    }
+
+    #[test]
+    fn test_general() {
+        // Both directions of 1-2 and 1-3, a one sided 2-4 and a diagonal
+        // entry.
+        let contents = "%%MatrixMarket matrix coordinate real general\n\
+                        4 4 6\n\
+                        1 2 1.0\n\
+                        2 1 1.0\n\
+                        3 1 2.0\n\
+                        1 3 2.0\n\
+                        2 4 1.0\n\
+                        4 4 1.0\n";
+        let (g, report) = read_str(contents).unwrap();
+        assert_eq!((report.edges, report.mirrored), (4, 2));
+        assert_eq!(g.get_node(&1).unwrap().get_neighbors(), &[2, 3]);
+        assert_eq!(g.get_node(&2).unwrap().get_neighbors(), &[1, 4]);
+        assert_eq!(g.get_node(&4).unwrap().get_neighbors(), &[2, 4]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let header = "%%MatrixMarket matrix coordinate real general\n";
+        let cases = [
+            ("%%MatrixMarket matrix array real general\n3 3\n", 1),
+            (
+                "%%MatrixMarket matrix coordinate real upper\n2 2 1\n1 2\n",
+                1,
+            ),
+            ("2 2 1\n1 2\n", 1),
+            (&format!("{}2 3 1\n1 2\n", header)[..], 2),
+            (&format!("{}2 2 1\n1 3\n", header)[..], 3),
+            (&format!("{}2 2 1\n1\n", header)[..], 3),
+        ];
+        for (contents, line) in cases.iter() {
+            match read_str(contents) {
+                Err(DeepwalkError::Parse { line: l, .. }) => assert_eq!(l, *line, "{}", contents),
+                other => panic!("expected a parse error, got {:?}", other),
+            }
+        }
+        assert!(matches!(
+            read_str(&format!("{}2 2 2\n1 2\n", header)),
+            Err(DeepwalkError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,268 @@
+//! A structured training log, one JSON object or CSV row per iteration and
+//! optionally every `metrics_every` walks.
+
+use crate::config::{Config, MetricsFormat};
+use crate::float::Float;
+use nalgebra::DMatrix;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+const CSV_HEADER: &str = "kind,iteration,walks,pairs,loss_per_pair,pairs_per_sec,epoch_ms,\
+elapsed_ms,learning_rate,subsampled,norm_mean,norm_min,norm_max,thread_utilization";
+
+/// Counters of the current iteration.  A pair is one prediction, a
+/// `(target, context)` pair for skip-gram and a window for CBOW.
+#[derive(Debug, Default, Clone)]
+pub struct Progress {
+    pub walks: usize,
+    pub pairs: u64,
+    pub loss: f64,
+    pub subsampled: usize,
+    /// Summed over threads, for the thread utilization.
+    pub busy: Duration,
+}
+
+impl Progress {
+    /// Adds the counters of one walk.
+    pub fn add_walk(&mut self, pairs: u64, loss: f64, subsampled: usize) {
+        self.walks += 1;
+        self.pairs += pairs;
+        self.loss += loss;
+        self.subsampled += subsampled;
+    }
+
+    /// The mean loss of a training pair, 0 before the first pair.
+    pub fn loss_per_pair(&self) -> f64 {
+        self.loss / (self.pairs.max(1) as f64)
+    }
+}
+
+/// L2 norms of the input vectors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormStats {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl NormStats {
+    pub fn new<T: Float>(input: &DMatrix<T>) -> NormStats {
+        NormStats::from_norms(input.column_iter().map(|c| c.norm().as_f64()))
+    }
+
+    /// The statistics of `norms`, without collecting them.
+    pub fn from_norms<I: IntoIterator<Item = f64>>(norms: I) -> NormStats {
+        let (mut count, mut sum) = (0, 0.0);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for norm in norms {
+            count += 1;
+            sum += norm;
+            min = min.min(norm);
+            max = max.max(norm);
+        }
+        if count == 0 {
+            return NormStats {
+                mean: 0.0,
+                min: 0.0,
+                max: 0.0,
+            };
+        }
+        NormStats {
+            mean: sum / count as f64,
+            min,
+            max,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Record {
+    /// `epoch` at the end of an iteration, `step` every `metrics_every` walks.
+    pub kind: &'static str,
+    pub iteration: usize,
+    pub walks: usize,
+    pub pairs: u64,
+    pub loss_per_pair: f64,
+    pub pairs_per_sec: f64,
+    pub epoch_ms: u64,
+    pub elapsed_ms: u64,
+    pub learning_rate: f64,
+    pub subsampled: usize,
+    pub norm_mean: Option<f64>,
+    pub norm_min: Option<f64>,
+    pub norm_max: Option<f64>,
+    pub thread_utilization: Option<f64>,
+}
+
+impl Record {
+    /// A record of the progress so far in iteration `iteration`, which
+    /// started at `epoch_start`.
+    pub fn step(
+        iteration: usize,
+        progress: &Progress,
+        epoch_start: Instant,
+        run_start: Instant,
+        learning_rate: f64,
+    ) -> Record {
+        let epoch = epoch_start.elapsed();
+        Record {
+            kind: "step",
+            iteration,
+            walks: progress.walks,
+            pairs: progress.pairs,
+            loss_per_pair: progress.loss_per_pair(),
+            pairs_per_sec: progress.pairs as f64 / epoch.as_secs_f64().max(1e-9),
+            epoch_ms: epoch.as_millis() as u64,
+            elapsed_ms: run_start.elapsed().as_millis() as u64,
+            learning_rate,
+            subsampled: progress.subsampled,
+            norm_mean: None,
+            norm_min: None,
+            norm_max: None,
+            thread_utilization: None,
+        }
+    }
+
+    /// The record of a finished iteration trained on `nthreads` threads.
+    pub fn epoch(
+        iteration: usize,
+        progress: &Progress,
+        epoch_start: Instant,
+        run_start: Instant,
+        learning_rate: f64,
+        norms: NormStats,
+        nthreads: usize,
+    ) -> Record {
+        let mut record = Record::step(iteration, progress, epoch_start, run_start, learning_rate);
+        let available = epoch_start.elapsed().as_secs_f64() * nthreads.max(1) as f64;
+        record.kind = "epoch";
+        record.norm_mean = Some(norms.mean);
+        record.norm_min = Some(norms.min);
+        record.norm_max = Some(norms.max);
+        record.thread_utilization =
+            Some((progress.busy.as_secs_f64() / available.max(1e-9)).min(1.0));
+        record
+    }
+
+    fn csv_row(&self) -> String {
+        let optional = |v: Option<f64>| v.map(|x| x.to_string()).unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.kind,
+            self.iteration,
+            self.walks,
+            self.pairs,
+            self.loss_per_pair,
+            self.pairs_per_sec,
+            self.epoch_ms,
+            self.elapsed_ms,
+            self.learning_rate,
+            self.subsampled,
+            optional(self.norm_mean),
+            optional(self.norm_min),
+            optional(self.norm_max),
+            optional(self.thread_utilization)
+        )
+    }
+}
+
+/// Whether a step record is due after `walks` walks, never if `every` is 0.
+pub fn step_due(every: usize, walks: usize) -> bool {
+    every > 0 && walks.is_multiple_of(every)
+}
+
+pub struct MetricsLog {
+    writer: BufWriter<File>,
+    format: MetricsFormat,
+    every: usize,
+}
+
+impl MetricsLog {
+    /// `None` if no `metrics_file` is configured.
+    pub fn from_config(config: &Config) -> io::Result<Option<MetricsLog>> {
+        if config.metrics_file().is_empty() {
+            return Ok(None);
+        }
+        let mut writer = BufWriter::new(File::create(config.metrics_file())?);
+        if config.metrics_format() == MetricsFormat::Csv {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(Some(MetricsLog {
+            writer,
+            format: config.metrics_format(),
+            every: config.metrics_every(),
+        }))
+    }
+
+    /// Write a step record every this many walks, 0 for none.
+    pub fn every(&self) -> usize {
+        self.every
+    }
+
+    /// Writes and flushes `record`, so a running log can be followed.
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            MetricsFormat::Jsonl => {
+                serde_json::to_writer(&mut self.writer, record)?;
+                writeln!(self.writer)?;
+            }
+            MetricsFormat::Csv => writeln!(self.writer, "{}", record.csv_row())?,
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    #[test]
+    fn test_norm_stats() {
+        let input = DMatrix::from_row_slice(2, 3, &[3.0, 0.0, 1.0, 4.0, 1.0, 0.0]);
+        let stats = NormStats::new(&input);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 5.0);
+        assert!((stats.mean - 7.0 / 3.0).abs() < 1e-12);
+        assert_eq!(NormStats::from_norms(vec![5.0, 1.0, 1.0]), stats);
+        assert_eq!(NormStats::from_norms(vec![]).max, 0.0);
+    }
+
+    #[test]
+    fn test_step_due() {
+        assert!(!step_due(0, 10));
+        assert!(step_due(5, 10));
+        assert!(!step_due(5, 11));
+    }
+
+    #[test]
+    fn test_records() {
+        let mut progress = Progress::default();
+        progress.add_walk(10, 5.0, 1);
+        progress.add_walk(30, 15.0, 0);
+        let start = Instant::now();
+        let step = Record::step(2, &progress, start, start, 0.01);
+        assert_eq!(step.walks, 2);
+        assert_eq!(step.pairs, 40);
+        assert_eq!(step.loss_per_pair, 0.5);
+        assert_eq!(step.subsampled, 1);
+        assert!(step.thread_utilization.is_none());
+        assert_eq!(
+            step.csv_row().split(',').count(),
+            CSV_HEADER.split(',').count()
+        );
+
+        let norms = NormStats {
+            mean: 1.0,
+            min: 0.5,
+            max: 2.0,
+        };
+        let epoch = Record::epoch(2, &progress, start, start, 0.01, norms, 4);
+        assert_eq!(epoch.kind, "epoch");
+        assert_eq!(epoch.norm_max, Some(2.0));
+        let json = serde_json::to_value(&epoch).unwrap();
+        assert_eq!(json["loss_per_pair"], 0.5);
+        assert_eq!(json["norm_min"], 0.5);
+    }
+}
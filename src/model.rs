@@ -1,89 +1,276 @@
-use crate::activation_functions::sigmoid;
-use crate::graph::Graph;
-use nalgebra::{DVector, Dynamic, Matrix, VecStorage};
+use crate::activation_functions::Sigmoid;
+use crate::config::{Objective, SigmoidMode};
+use crate::float::Float;
+use crate::kernels;
+use nalgebra::DMatrix;
 use rand::distributions::Uniform;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
+use std::fmt::Display;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufWriter, Write};
 use std::path::Path;
 
-type DMatrixf64 = Matrix<f64, Dynamic, Dynamic, VecStorage<f64, Dynamic, Dynamic>>;
-
-pub struct Model {
-    weight_mat: DMatrixf64,
-    output_mat: DMatrixf64,
+pub struct Model<T: Float> {
+    weight_mat: DMatrix<T>,
+    output_mat: DMatrix<T>,
     vec_dim: usize,
+    sigmoid: Sigmoid<T>,
+    objective: Objective,
+    // Scratch buffers for the hidden layer and its gradient, reused by the
+    // steps.
+    h: Vec<T>,
+    h_update: Vec<T>,
 }
 
-impl Model {
-    pub fn new(num_nodes: usize, vec_dim: usize) -> Model {
+impl<T: Float> Model<T> {
+    pub fn new(num_nodes: usize, num_outputs: usize, vec_dim: usize) -> Model<T> {
         let runif = Uniform::new(-0.5 / (vec_dim as f64), 0.5 / (vec_dim as f64));
         let mut rng = thread_rng();
-        let weight_mat = DMatrixf64::from_distribution(vec_dim, num_nodes, &runif, &mut rng);
-        let output_mat = DMatrixf64::zeros(vec_dim, num_nodes - 1);
+        let weight_mat = DMatrix::from_fn(vec_dim, num_nodes, |_, _| T::cast(rng.sample(runif)));
+        let output_mat = DMatrix::zeros(vec_dim, num_outputs);
 
         Model {
             weight_mat,
             output_mat,
             vec_dim,
+            sigmoid: Sigmoid::new(SigmoidMode::Table),
+            objective: Objective::HierarchicalSoftmax,
+            h: vec![T::zero(); vec_dim],
+            h_update: vec![T::zero(); vec_dim],
         }
     }
 
+    pub fn set_sigmoid_mode(&mut self, mode: SigmoidMode) {
+        self.sigmoid = Sigmoid::new(mode);
+    }
+
+    pub fn sigmoid_mode(&self) -> SigmoidMode {
+        self.sigmoid.mode()
+    }
+
+    /// Sets how outputs outside the clipped sigmoid range are handled, see
+    /// `update_outputs`.
+    pub fn set_objective(&mut self, objective: Objective) {
+        self.objective = objective;
+    }
+
+    /// Skip-gram step, predicts the outputs from the input vector of
+    /// `node_idx`.
     pub fn step(
         &mut self,
         node_idx: usize,
-        outcomes: Vec<(usize, f64)>,
+        points: &[usize],
+        codes: &[f64],
+        learning_rate: f64,
+    ) -> f64 {
+        let learning_rate = T::cast(learning_rate);
+        let dim = self.vec_dim;
+        let node_vec = &self.weight_mat.as_slice()[node_idx * dim..(node_idx + 1) * dim];
+        let error = update_outputs(
+            &mut self.output_mat,
+            &self.sigmoid,
+            self.objective,
+            node_vec,
+            points,
+            codes,
+            learning_rate,
+            &mut self.h_update,
+        );
+        let node_vec = &mut self.weight_mat.as_mut_slice()[node_idx * dim..(node_idx + 1) * dim];
+        T::axpy(-learning_rate, &self.h_update, node_vec);
+        error
+    }
+
+    /// CBOW step, predicts the outputs from the sum or mean of the input
+    /// vectors of `context_idxs`.
+    pub fn step_cbow(
+        &mut self,
+        context_idxs: &[usize],
+        points: &[usize],
+        codes: &[f64],
         learning_rate: f64,
+        mean: bool,
     ) -> f64 {
-        let node_vec = self.weight_mat.column(node_idx);
-        let mut error = 0.0;
-        let mut h_update = DVector::from_element(self.vec_dim, 0.0);
-        for (idx, outcome) in outcomes {
-            let mut out_vec = self.output_mat.column_mut(idx);
-            let nv_dot_ov = out_vec.dot(&node_vec);
-
-            error += sigmoid(outcome * nv_dot_ov).ln();
-
-            // Derivative of error with respect to out_vec*node_vec
-            let de_dvh = if outcome == 1.0 {
-                sigmoid(nv_dot_ov) - 1.0
-            } else {
-                sigmoid(nv_dot_ov)
-            };
-
-            h_update.axpy(de_dvh, &out_vec, 1.0);
-            out_vec.axpy(-1.0 * learning_rate * de_dvh, &node_vec, 1.0);
+        let learning_rate = T::cast(learning_rate);
+        let dim = self.vec_dim;
+        self.h.fill(T::zero());
+        for &idx in context_idxs {
+            let context_vec = &self.weight_mat.as_slice()[idx * dim..(idx + 1) * dim];
+            T::axpy(T::one(), context_vec, &mut self.h);
+        }
+        if mean && !context_idxs.is_empty() {
+            kernels::scale(T::one() / T::cast(context_idxs.len() as f64), &mut self.h);
+        }
+        let error = update_outputs(
+            &mut self.output_mat,
+            &self.sigmoid,
+            self.objective,
+            &self.h,
+            points,
+            codes,
+            learning_rate,
+            &mut self.h_update,
+        );
+        for &idx in context_idxs {
+            let context_vec = &mut self.weight_mat.as_mut_slice()[idx * dim..(idx + 1) * dim];
+            T::axpy(-learning_rate, &self.h_update, context_vec);
         }
-        let mut node_vec = self.weight_mat.column_mut(node_idx);
-        node_vec.axpy(-1.0 * learning_rate, &h_update, 1.0);
-        -1.0 * error
+        error
     }
 
-    pub fn write_weight_mat<P: AsRef<Path>>(&self, weight_file: &P, graph: Graph) {
-        let mut f = File::create(weight_file).expect("Unable to create output file for weights");
-        for (node_id, node_idx) in graph.get_node_id_to_idx().iter() {
-            write!(f, "{}", node_id).expect("Writing to the weight file errored");
-            let node_vec = &self.weight_mat.column(*node_idx);
+    /// The input vectors, one column per node.
+    pub fn input_matrix(&self) -> DMatrix<T> {
+        self.weight_mat.clone()
+    }
+
+    /// The L2 norms of the input vectors, without copying them.
+    pub fn input_norms(&self) -> impl Iterator<Item = f64> + '_ {
+        self.weight_mat.column_iter().map(|c| c.norm().as_f64())
+    }
+
+    /// The output vectors, one column per output of the output layer.
+    pub fn output_matrix(&self) -> DMatrix<T> {
+        self.output_mat.clone()
+    }
+
+    /// Writes the vector of node index `i` on a line starting with `ids[i]`.
+    pub fn write_weight_mat<P: AsRef<Path>, D: Display>(
+        &self,
+        weight_file: &P,
+        ids: &[D],
+    ) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(weight_file)?);
+        for (idx, id) in ids.iter().enumerate() {
+            write!(f, "{}", id)?;
+            let node_vec = &self.weight_mat.column(idx);
             for i in 0..node_vec.shape().0 {
-                write!(f, " {}", node_vec[(i, 0)]).expect("Writing to the weight file errored");
+                write!(f, " {}", node_vec[(i, 0)])?;
             }
-            write!(f, "\n").expect("Writing to the weight file errored");
+            writeln!(f)?;
         }
+        f.flush()
     }
 }
 
+/// Updates the output vectors for the hidden layer `h` and accumulates the
+/// gradient of `h` into `h_update`.  Returns the error.
+///
+/// Outside the clipped sigmoid range hierarchical softmax skips the output
+/// like word2vec, while negative sampling clamps the sigmoid so a
+/// saturated wrong sample still gets the full gradient.
+#[allow(clippy::too_many_arguments)]
+fn update_outputs<T: Float>(
+    output_mat: &mut DMatrix<T>,
+    sigmoid: &Sigmoid<T>,
+    objective: Objective,
+    h: &[T],
+    points: &[usize],
+    codes: &[f64],
+    learning_rate: T,
+    h_update: &mut [T],
+) -> f64 {
+    let dim = h.len();
+    let mut error = 0.0;
+    h_update.fill(T::zero());
+    for (&idx, &outcome) in points.iter().zip(codes) {
+        let out_vec = &mut output_mat.as_mut_slice()[idx * dim..(idx + 1) * dim];
+        let nv_dot_ov = T::dot(out_vec, h);
+
+        error += sigmoid.log(T::cast(outcome) * nv_dot_ov).as_f64();
+
+        let sigmoid = match objective {
+            Objective::HierarchicalSoftmax => match sigmoid.clipped(nv_dot_ov) {
+                Some(s) => s,
+                None => continue,
+            },
+            Objective::NegativeSampling => sigmoid.clamped(nv_dot_ov),
+        };
+        // Derivative of error with respect to out_vec*node_vec
+        let de_dvh = if outcome == 1.0 {
+            sigmoid - T::one()
+        } else {
+            sigmoid
+        };
+
+        T::axpy(de_dvh, out_vec, h_update);
+        T::axpy(-learning_rate * de_dvh, h, out_vec);
+    }
+    -error
+}
+
 #[cfg(test)]
 mod model_tests {
     use super::*;
 
     #[test]
     fn test_model() {
-        let mut model = Model::new(3, 5);
-        model.step(0, vec![(0, 1.0)], 0.5);
+        let mut model: Model<f64> = Model::new(3, 2, 5);
+        model.step(0, &[0], &[1.0], 0.5);
+
+        let mut model: Model<f32> = Model::new(3, 2, 5);
+        assert!(model.step(0, &[0, 1], &[1.0, -1.0], 0.5) > 0.0);
+    }
+
+    #[test]
+    fn test_cbow_step() {
+        let mut model: Model<f64> = Model::new(3, 3, 4);
+        let before = model.weight_mat.clone();
+        for _ in 0..50 {
+            model.step_cbow(&[0, 1], &[2, 0], &[1.0, -1.0], 0.5, true);
+        }
+        // Only the context vectors are trained.
+        assert_ne!(model.weight_mat.column(0), before.column(0));
+        assert_ne!(model.weight_mat.column(1), before.column(1));
+        assert_eq!(model.weight_mat.column(2), before.column(2));
+        assert!(model.step_cbow(&[0, 1], &[2, 0], &[1.0, -1.0], 0.5, false) < 0.1);
+    }
+
+    #[test]
+    fn test_scratch_reset() {
+        // Stale NaNs in the scratch buffers don't leak into the next step.
+        let mut model: Model<f64> = Model::new(3, 3, 4);
+        model.h.fill(f64::NAN);
+        model.h_update.fill(f64::NAN);
+        model.step_cbow(&[0, 1], &[2], &[1.0], 0.5, true);
+        assert!(model.weight_mat.iter().all(|x| x.is_finite()));
+        assert!(model.output_mat.iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn test_saturated_step() {
+        let mut model: Model<f64> = Model::new(2, 1, 2);
+        model.weight_mat.fill(10.0);
+        model.output_mat.fill(10.0);
+
+        // The error stays finite and hierarchical softmax skips the clipped
+        // update.
+        let error = model.step(0, &[0], &[-1.0], 0.5);
+        assert!((error - 200.0).abs() < 1e-9);
+        assert_eq!(model.output_mat[(0, 0)], 10.0);
+
+        model.set_sigmoid_mode(SigmoidMode::Exact);
+        model.step(0, &[0], &[-1.0], 0.5);
+        assert!(model.output_mat[(0, 0)] < 10.0);
+    }
+
+    #[test]
+    fn test_saturated_negative_sample() {
+        let mut model: Model<f64> = Model::new(2, 2, 2);
+        model.set_objective(Objective::NegativeSampling);
+        model.weight_mat.fill(10.0);
+        model.output_mat.fill(10.0);
+
+        // A confidently wrong negative sample gets the full gradient, a
+        // confidently right positive one none.
+        model.step(0, &[0, 1], &[-1.0, 1.0], 0.5);
+        assert!((model.output_mat[(0, 0)] - 5.0).abs() < 1e-9);
+        assert_eq!(model.output_mat[(0, 1)], 10.0);
+        assert!(model.weight_mat[(0, 0)] < 10.0);
     }
 
     #[test]
     fn test_vec() {
+        use nalgebra::DVector;
         let v1 = DVector::from_element(3, 1.0);
         let v2 = v1 * 3.0;
 
@@ -1,36 +1,56 @@
-use crate::activation_functions::sigmoid;
-use crate::graph::Graph;
-use nalgebra::DVector;
+use crate::activation_functions::Sigmoid;
+use crate::config::{Objective, SigmoidMode};
+use crate::float::Float;
+use crate::kernels;
+use nalgebra::{DMatrix, DVector};
 use rand::distributions::Uniform;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
+use std::fmt::Display;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufWriter, Write};
 use std::path::Path;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{Arc, RwLock};
 
-type ConcurrentDVecf64 = Arc<RwLock<DVector<f64>>>;
+type ConcurrentDVec<T> = Arc<RwLock<DVector<T>>>;
 
-pub struct ConcurrentModel {
-    pub weight_mat: Arc<Vec<ConcurrentDVecf64>>,
-    pub output_mat: Arc<Vec<ConcurrentDVecf64>>,
+/// Per thread scratch buffers for the hidden layer and its gradient.
+pub struct StepBuffers<T: Float> {
+    h: Vec<T>,
+    h_update: Vec<T>,
+}
+
+impl<T: Float> StepBuffers<T> {
+    pub fn new(vec_dim: usize) -> StepBuffers<T> {
+        StepBuffers {
+            h: vec![T::zero(); vec_dim],
+            h_update: vec![T::zero(); vec_dim],
+        }
+    }
+}
+
+pub struct ConcurrentModel<T: Float> {
+    pub weight_mat: Arc<Vec<ConcurrentDVec<T>>>,
+    pub output_mat: Arc<Vec<ConcurrentDVec<T>>>,
     pub vec_dim: usize,
+    sigmoid: Sigmoid<T>,
+    objective: Objective,
 }
 
-impl ConcurrentModel {
-    pub fn new(num_nodes: usize, vec_dim: usize) -> ConcurrentModel {
+impl<T: Float> ConcurrentModel<T> {
+    pub fn new(num_nodes: usize, num_outputs: usize, vec_dim: usize) -> ConcurrentModel<T> {
         let runif = Uniform::new(-0.5 / (vec_dim as f64), 0.5 / (vec_dim as f64));
         let mut rng = thread_rng();
 
         let mut weight_mat = Vec::with_capacity(num_nodes);
         for _ in 0..num_nodes {
-            let dv = DVector::from_distribution(vec_dim, &runif, &mut rng);
+            let dv = DVector::from_fn(vec_dim, |_, _| T::cast(rng.sample(runif)));
             let dv = Arc::new(RwLock::new(dv));
             weight_mat.push(dv);
         }
         let weight_mat = Arc::new(weight_mat);
 
-        let mut output_mat = Vec::with_capacity(num_nodes - 1);
-        for _ in 0..(num_nodes - 1) {
+        let mut output_mat = Vec::with_capacity(num_outputs);
+        for _ in 0..num_outputs {
             output_mat.push(Arc::new(RwLock::new(DVector::zeros(vec_dim))));
         }
 
@@ -40,65 +60,217 @@ impl ConcurrentModel {
             weight_mat,
             output_mat,
             vec_dim,
+            sigmoid: Sigmoid::new(SigmoidMode::Table),
+            objective: Objective::HierarchicalSoftmax,
         }
     }
 
-    pub fn write_weight_mat<P: AsRef<Path>>(&self, weight_file: &P, graph: Arc<Graph>) {
-        let mut f = File::create(weight_file).expect("Unable to create output file for weights");
-        for (node_id, node_idx) in graph.get_node_id_to_idx().iter() {
-            write!(f, "{}", node_id).expect("Writing to the weight file errored");
-            let node_vec = &self.weight_mat[*node_idx].read().unwrap();
+    pub fn set_sigmoid_mode(&mut self, mode: SigmoidMode) {
+        self.sigmoid = Sigmoid::new(mode);
+    }
+
+    /// Sets how outputs outside the clipped sigmoid range are handled, see
+    /// `update_outputs`.
+    pub fn set_objective(&mut self, objective: Objective) {
+        self.objective = objective;
+    }
+
+    /// The input vectors, one column per node.
+    pub fn input_matrix(&self) -> DMatrix<T> {
+        to_matrix(&self.weight_mat, self.vec_dim)
+    }
+
+    /// The L2 norms of the input vectors, reading one vector at a time.
+    pub fn input_norms(&self) -> impl Iterator<Item = f64> + '_ {
+        self.weight_mat
+            .iter()
+            .map(|v| v.read().unwrap().norm().as_f64())
+    }
+
+    /// The output vectors, one column per output of the output layer.
+    pub fn output_matrix(&self) -> DMatrix<T> {
+        to_matrix(&self.output_mat, self.vec_dim)
+    }
+
+    /// Writes the vector of node index `i` on a line starting with `ids[i]`.
+    pub fn write_weight_mat<P: AsRef<Path>, D: Display>(
+        &self,
+        weight_file: &P,
+        ids: &[D],
+    ) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(weight_file)?);
+        for (idx, id) in ids.iter().enumerate() {
+            write!(f, "{}", id)?;
+            let node_vec = &self.weight_mat[idx].read().unwrap();
             for i in 0..node_vec.shape().0 {
-                write!(f, " {}", node_vec[(i, 0)]).expect("Writing to the weight file errored");
+                write!(f, " {}", node_vec[(i, 0)])?;
             }
-            write!(f, "\n").expect("Writing to the weight file errored");
+            writeln!(f)?;
         }
+        f.flush()
     }
 
     pub fn step(
         &self,
         node_idx: usize,
-        outcomes: &Vec<(usize, f64)>,
+        points: &[usize],
+        codes: &[f64],
         learning_rate: f64,
-        error: Arc<Mutex<f64>>,
-        vec_dim: usize,
-    ) {
-        let mut err = 0.0;
-        let mut h_update = DVector::from_element(vec_dim, 0.0);
-        {
+        buffers: &mut StepBuffers<T>,
+    ) -> f64 {
+        let learning_rate = T::cast(learning_rate);
+        let h_update = &mut buffers.h_update;
+        let err = {
             let node_vec = self.weight_mat[node_idx].read().unwrap();
+            self.update_outputs(
+                &self.output_mat,
+                node_vec.as_slice(),
+                points,
+                codes,
+                learning_rate,
+                h_update,
+            )
+        };
+        {
+            let mut node_vec = self.weight_mat[node_idx].write().unwrap();
+            T::axpy(-learning_rate, h_update, node_vec.as_mut_slice());
+        }
+        err
+    }
 
-            for (out_idx, outcome) in outcomes {
-                let mut out_vec = self.output_mat[*out_idx].write().unwrap();
-
-                let nv_dot_ov = out_vec.dot(&node_vec);
-                let e = sigmoid(outcome * nv_dot_ov).ln();
-                err += e;
+    /// First order LINE step, the outputs are the input vectors of
+    /// `points` rather than the output vectors.
+    pub fn step_first_order(
+        &self,
+        node_idx: usize,
+        points: &[usize],
+        codes: &[f64],
+        learning_rate: f64,
+        buffers: &mut StepBuffers<T>,
+    ) -> f64 {
+        let learning_rate = T::cast(learning_rate);
+        let StepBuffers { h, h_update } = buffers;
+        // A copy, the outputs may include `node_idx` itself.
+        h.copy_from_slice(self.weight_mat[node_idx].read().unwrap().as_slice());
+        let err = self.update_outputs(&self.weight_mat, h, points, codes, learning_rate, h_update);
+        let mut node_vec = self.weight_mat[node_idx].write().unwrap();
+        T::axpy(-learning_rate, h_update, node_vec.as_mut_slice());
+        err
+    }
 
-                let tj = if *outcome == 1.0 { 1.0 } else { 0.0 };
-                let de_dvh = sigmoid(nv_dot_ov) - tj;
-                h_update.axpy(1.0 * de_dvh, &out_vec, 1.0);
-                out_vec.axpy(-1.0 * de_dvh * learning_rate, &node_vec, 1.0);
-            }
+    /// CBOW step, predicts the outputs from the sum or mean of the input
+    /// vectors of `context_idxs`.
+    pub fn step_cbow(
+        &self,
+        context_idxs: &[usize],
+        points: &[usize],
+        codes: &[f64],
+        learning_rate: f64,
+        mean: bool,
+        buffers: &mut StepBuffers<T>,
+    ) -> f64 {
+        let learning_rate = T::cast(learning_rate);
+        let StepBuffers { h, h_update } = buffers;
+        h.fill(T::zero());
+        for &idx in context_idxs {
+            let context_vec = self.weight_mat[idx].read().unwrap();
+            T::axpy(T::one(), context_vec.as_slice(), h);
         }
-        {
-            let mut node_vec = self.weight_mat[node_idx].write().unwrap();
-            node_vec.axpy(-1.0 * learning_rate, &h_update, 1.0);
+        if mean && !context_idxs.is_empty() {
+            kernels::scale(T::one() / T::cast(context_idxs.len() as f64), h);
         }
-        {
-            let mut error = error.lock().unwrap();
-            *error += -1.0 * err;
+        let err = self.update_outputs(&self.output_mat, h, points, codes, learning_rate, h_update);
+        for &idx in context_idxs {
+            let mut context_vec = self.weight_mat[idx].write().unwrap();
+            T::axpy(-learning_rate, h_update, context_vec.as_mut_slice());
+        }
+        err
+    }
+
+    /// Like `model::update_outputs`, hierarchical softmax skips outputs
+    /// outside the clipped sigmoid range and negative sampling clamps them.
+    fn update_outputs(
+        &self,
+        outputs: &[ConcurrentDVec<T>],
+        h: &[T],
+        points: &[usize],
+        codes: &[f64],
+        learning_rate: T,
+        h_update: &mut [T],
+    ) -> f64 {
+        let mut err = 0.0;
+        h_update.fill(T::zero());
+        for (out_idx, outcome) in points.iter().zip(codes) {
+            let mut out_vec = outputs[*out_idx].write().unwrap();
+            let out_vec = out_vec.as_mut_slice();
+
+            let nv_dot_ov = T::dot(out_vec, h);
+            let e = self.sigmoid.log(T::cast(*outcome) * nv_dot_ov);
+            err += e.as_f64();
+
+            let sigmoid = match self.objective {
+                Objective::HierarchicalSoftmax => match self.sigmoid.clipped(nv_dot_ov) {
+                    Some(s) => s,
+                    None => continue,
+                },
+                Objective::NegativeSampling => self.sigmoid.clamped(nv_dot_ov),
+            };
+            let tj = if *outcome == 1.0 { T::one() } else { T::zero() };
+            let de_dvh = sigmoid - tj;
+            T::axpy(de_dvh, out_vec, h_update);
+            T::axpy(-de_dvh * learning_rate, h, out_vec);
         }
+        -err
     }
 }
 
+fn to_matrix<T: Float>(vectors: &[ConcurrentDVec<T>], vec_dim: usize) -> DMatrix<T> {
+    let mut mat = DMatrix::zeros(vec_dim, vectors.len());
+    for (i, v) in vectors.iter().enumerate() {
+        mat.set_column(i, &*v.read().unwrap());
+    }
+    mat
+}
+
 #[cfg(test)]
 mod model_tests {
     use super::*;
 
     #[test]
     fn test_model() {
-        let _model = ConcurrentModel::new(3, 5);
+        let _model: ConcurrentModel<f64> = ConcurrentModel::new(3, 2, 5);
+        let model: ConcurrentModel<f32> = ConcurrentModel::new(3, 2, 5);
+        let mut buffers = StepBuffers::new(5);
+        assert!(model.step(0, &[0, 1], &[1.0, -1.0], 0.5, &mut buffers) > 0.0);
+        let err = model.step_cbow(&[1, 2], &[0], &[1.0], 0.5, true, &mut buffers);
+        assert!(err > 0.0);
+    }
+
+    #[test]
+    fn test_saturated_negative_sample() {
+        let mut model: ConcurrentModel<f64> = ConcurrentModel::new(1, 1, 2);
+        model.weight_mat[0].write().unwrap().fill(10.0);
+        model.output_mat[0].write().unwrap().fill(10.0);
+        let mut buffers = StepBuffers::new(2);
+
+        model.step(0, &[0], &[-1.0], 0.5, &mut buffers);
+        assert_eq!(model.output_mat[0].read().unwrap()[0], 10.0);
+        model.set_objective(Objective::NegativeSampling);
+        model.step(0, &[0], &[-1.0], 0.5, &mut buffers);
+        assert!((model.output_mat[0].read().unwrap()[0] - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_first_order_step() {
+        let model: ConcurrentModel<f64> = ConcurrentModel::new(3, 0, 4);
+        let mut buffers = StepBuffers::new(4);
+        let before = model.input_matrix();
+        for _ in 0..50 {
+            model.step_first_order(0, &[1, 2], &[1.0, -1.0], 0.5, &mut buffers);
+        }
+        let after = model.input_matrix();
+        assert_ne!(after.column(1), before.column(1));
+        assert!(after.column(0).dot(&after.column(1)) > after.column(0).dot(&after.column(2)));
     }
 
     #[test]
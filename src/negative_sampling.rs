@@ -0,0 +1,87 @@
+use crate::alias::AliasTable;
+use crate::graph::Graph;
+use rand::Rng;
+
+/// The power applied to node frequencies in the noise distribution.
+pub const NOISE_POWER: f64 = 0.75;
+
+/// Draws negative samples from the unigram distribution raised to
+/// `NOISE_POWER`, as in word2vec.
+#[derive(Debug)]
+pub struct NegativeSampler {
+    table: AliasTable,
+}
+
+impl NegativeSampler {
+    /// `frequencies[i]` is the frequency of the node with index `i`.
+    pub fn new(frequencies: &[f64]) -> NegativeSampler {
+        let weights: Vec<f64> = frequencies.iter().map(|f| f.powf(NOISE_POWER)).collect();
+        NegativeSampler {
+            table: AliasTable::new(&weights),
+        }
+    }
+
+    /// Uses weighted node degrees as the frequencies.
+    pub fn from_graph(graph: &Graph) -> NegativeSampler {
+        let degrees: Vec<f64> = graph
+            .node_ids_by_idx()
+            .iter()
+            .map(|id| graph.get_node(id).unwrap().get_weighted_degree())
+            .collect();
+        NegativeSampler::new(&degrees)
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        self.table.sample(rng)
+    }
+
+    /// Fills `points` and `codes` with the positive node labelled 1 followed
+    /// by `negative` noise nodes labelled -1.  Noise equal to the positive
+    /// node is skipped.
+    pub fn fill<R: Rng>(
+        &self,
+        positive: usize,
+        negative: usize,
+        rng: &mut R,
+        points: &mut Vec<usize>,
+        codes: &mut Vec<f64>,
+    ) {
+        points.clear();
+        codes.clear();
+        points.push(positive);
+        codes.push(1.0);
+        for _ in 0..negative {
+            let noise = self.sample(rng);
+            if noise != positive {
+                points.push(noise);
+                codes.push(-1.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod negative_sampling_tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_fill() {
+        let sampler = NegativeSampler::new(&[0.0, 1.0, 1.0]);
+        let (mut points, mut codes) = (Vec::new(), Vec::new());
+        sampler.fill(2, 10, &mut thread_rng(), &mut points, &mut codes);
+        assert_eq!(points[0], 2);
+        assert_eq!(codes[0], 1.0);
+        assert!(points[1..].iter().all(|p| *p == 1));
+        assert_eq!(points.len(), codes.len());
+    }
+
+    #[test]
+    fn test_noise_distribution() {
+        let sampler = NegativeSampler::new(&[1.0, 16.0]);
+        let mut rng = thread_rng();
+        let hits = (0..20000).filter(|_| sampler.sample(&mut rng) == 1).count();
+        // 16^0.75 = 8
+        assert!((hits as f64 / 20000.0 - 8.0 / 9.0).abs() < 0.02);
+    }
+}
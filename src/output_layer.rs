@@ -0,0 +1,45 @@
+use crate::huffman_tree::HuffmanTree;
+use crate::negative_sampling::NegativeSampler;
+use rand::Rng;
+
+/// What a training step predicts: the Huffman path of a node for
+/// hierarchical softmax, or the node itself against noise nodes for
+/// negative sampling.
+pub enum OutputLayer {
+    HierarchicalSoftmax(HuffmanTree),
+    NegativeSampling {
+        sampler: NegativeSampler,
+        negative: usize,
+        num_nodes: usize,
+    },
+}
+
+impl OutputLayer {
+    /// The number of output vectors the model needs.
+    pub fn num_outputs(&self) -> usize {
+        match self {
+            OutputLayer::HierarchicalSoftmax(tree) => tree.num_inner_nodes(),
+            OutputLayer::NegativeSampling { num_nodes, .. } => *num_nodes,
+        }
+    }
+
+    /// The output indices and labels to predict `node_idx`.  Negative
+    /// samples are written to the scratch `points` and `codes`.
+    pub fn outputs<'a, R: Rng>(
+        &'a self,
+        node_idx: usize,
+        rng: &mut R,
+        points: &'a mut Vec<usize>,
+        codes: &'a mut Vec<f64>,
+    ) -> (&'a [usize], &'a [f64]) {
+        match self {
+            OutputLayer::HierarchicalSoftmax(tree) => tree.get_points_and_codes(node_idx),
+            OutputLayer::NegativeSampling {
+                sampler, negative, ..
+            } => {
+                sampler.fill(node_idx, *negative, rng, points, codes);
+                (points, codes)
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Matrix factorization baselines on the dense adjacency matrix, for small
+//! and medium graphs.
+//!
+//! NetMF (Qiu et al. 2018) factorizes the matrix DeepWalk implicitly
+//! factorizes with a truncated randomized SVD (Halko et al. 2011).
+//! Laplacian eigenmaps use the eigenvectors of the smallest non trivial
+//! eigenvalues of the normalized Laplacian.  Both are deterministic.
+
+use crate::config::{Config, Method, Precision};
+use crate::embeddings;
+use crate::error::DeepwalkError;
+use crate::graph::Graph;
+use log::info;
+use nalgebra::{DMatrix, DVector, SymmetricEigen, SVD};
+use rand::distributions::Uniform;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp;
+use std::fs::File;
+use std::io::Write;
+use std::time::Instant;
+
+/// Extra columns sampled by the randomized SVD beyond the target rank.
+const OVERSAMPLES: usize = 10;
+/// Power iterations of the randomized SVD, sharpen a slowly decaying
+/// spectrum.
+const POWER_ITERATIONS: usize = 2;
+const SEED: u64 = 42;
+
+/// Computes the embedding for `config.method()` and writes the weight and
+/// perf files, each vector after its node's `labels`.
+pub fn run(graph: &Graph, labels: &[String], config: &Config) -> Result<(), DeepwalkError> {
+    let limit = config.max_dense_nodes();
+    if limit > 0 && graph.num_nodes() > limit {
+        return Err(DeepwalkError::Config(format!(
+            "{:?} builds dense {} x {} matrices, max_dense_nodes is {}",
+            config.method(),
+            graph.num_nodes(),
+            graph.num_nodes(),
+            limit
+        )));
+    }
+    let now = Instant::now();
+    let vectors = match config.method() {
+        Method::Netmf => netmf(
+            graph,
+            config.window_size(),
+            config.negative(),
+            config.vector_dim(),
+        ),
+        Method::LaplacianEigenmaps => laplacian_eigenmaps(graph, config.vector_dim()),
+        method => {
+            return Err(DeepwalkError::Config(format!(
+                "{:?} is not a factorization method",
+                method
+            )))
+        }
+    };
+    info!("Factorization took {} ms", now.elapsed().as_millis());
+
+    match config.precision() {
+        Precision::F32 => {
+            let vectors = vectors.map(|x| x as f32);
+            embeddings::write_vectors(&config.weight_file(), labels, &vectors)?;
+        }
+        Precision::F64 => embeddings::write_vectors(&config.weight_file(), labels, &vectors)?,
+    }
+    let mut f = File::create(config.perf_file())?;
+    writeln!(f, "time")?;
+    writeln!(f, "{}", now.elapsed().as_secs())?;
+    Ok(())
+}
+
+/// The symmetric weighted adjacency matrix in node index order.  Parallel
+/// edges add up and a self loop counts once, like in the neighbor lists.
+pub fn adjacency_matrix(graph: &Graph) -> DMatrix<f64> {
+    let n = graph.num_nodes();
+    let mut a = DMatrix::zeros(n, n);
+    for (i, node_id) in graph.node_ids_by_idx().iter().enumerate() {
+        let node = graph.get_node(node_id).unwrap();
+        for (e, neighbor) in node.get_neighbors().iter().enumerate() {
+            let weight = node.get_edge_weights().map_or(1.0, |w| w[e] as f64);
+            a[(i, *graph.get_node_idx(neighbor).unwrap())] += weight;
+        }
+    }
+    a
+}
+
+/// NetMF for window size `window`, embedding `dim` columns.  Factorizes
+/// `log(max(1, vol(G) / (b T) (sum_{r=1}^T P^r) D^-1))` with `P = D^-1 A`
+/// and returns `U_d sqrt(S_d)` transposed, one column per node.
+pub fn netmf(graph: &Graph, window: usize, negative: usize, dim: usize) -> DMatrix<f64> {
+    let a = adjacency_matrix(graph);
+    let n = a.nrows();
+    let degrees: Vec<f64> = a.row_sum().iter().cloned().collect();
+    let volume: f64 = degrees.iter().sum();
+    let inv_degree = |d: f64| if d > 0.0 { 1.0 / d } else { 0.0 };
+
+    let p = DMatrix::from_fn(n, n, |i, j| a[(i, j)] * inv_degree(degrees[i]));
+    let mut power = p.clone();
+    let mut sum = p.clone();
+    for _ in 1..window {
+        power = &power * &p;
+        sum += &power;
+    }
+    let scale = volume / (cmp::max(negative, 1) * cmp::max(window, 1)) as f64;
+    let m = DMatrix::from_fn(n, n, |i, j| {
+        let x = scale * sum[(i, j)] * inv_degree(degrees[j]);
+        if x > 1.0 {
+            x.ln()
+        } else {
+            0.0
+        }
+    });
+
+    let (u, s) = randomized_svd(&m, dim);
+    let mut vectors = DMatrix::zeros(dim, n);
+    for k in 0..s.len() {
+        let weight = s[k].sqrt();
+        for i in 0..n {
+            vectors[(k, i)] = u[(i, k)] * weight;
+        }
+    }
+    vectors
+}
+
+/// Laplacian eigenmaps, the generalized eigenvectors `D^-1/2 v` of the
+/// normalized Laplacian for the `dim` smallest eigenvalues after the
+/// trivial one.  Returns one column per node, missing dimensions of graphs
+/// with too few nodes are zero.
+pub fn laplacian_eigenmaps(graph: &Graph, dim: usize) -> DMatrix<f64> {
+    let a = adjacency_matrix(graph);
+    let n = a.nrows();
+    let inv_sqrt: Vec<f64> = a
+        .row_sum()
+        .iter()
+        .map(|d| if *d > 0.0 { 1.0 / d.sqrt() } else { 0.0 })
+        .collect();
+    let laplacian = DMatrix::from_fn(n, n, |i, j| {
+        let identity = if i == j { 1.0 } else { 0.0 };
+        identity - inv_sqrt[i] * a[(i, j)] * inv_sqrt[j]
+    });
+
+    let eigen = SymmetricEigen::new(laplacian);
+    let order = sorted_indices(&eigen.eigenvalues, false);
+    let mut vectors = DMatrix::zeros(dim, n);
+    for (k, &e) in order.iter().skip(1).take(dim).enumerate() {
+        let v = canonical_sign(eigen.eigenvectors.column(e).into_owned());
+        for i in 0..n {
+            vectors[(k, i)] = v[i] * inv_sqrt[i];
+        }
+    }
+    vectors
+}
+
+/// A rank `k` approximation `U diag(s) V^T` of `m`, returns `U` and `s`
+/// with the singular values in decreasing order.  The sign of each
+/// singular vector is fixed so repeated runs agree.
+pub fn randomized_svd(m: &DMatrix<f64>, k: usize) -> (DMatrix<f64>, DVector<f64>) {
+    let (rows, cols) = m.shape();
+    let samples = cmp::min(k + OVERSAMPLES, cmp::min(rows, cols));
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let uniform = Uniform::new(-1.0, 1.0);
+    let omega = DMatrix::from_fn(cols, samples, |_, _| rng.sample(uniform));
+
+    // Range finder, re-orthonormalized after every product.
+    let mut q = (m * omega).qr().q();
+    for _ in 0..POWER_ITERATIONS {
+        let z = (m.transpose() * &q).qr().q();
+        q = (m * z).qr().q();
+    }
+
+    let b = q.transpose() * m;
+    let svd = SVD::new(b, true, false);
+    let u_b = svd.u.unwrap();
+    let order = sorted_indices(&svd.singular_values, true);
+    let rank = cmp::min(k, order.len());
+    let mut u = DMatrix::zeros(rows, rank);
+    let mut s = DVector::zeros(rank);
+    for (c, &idx) in order.iter().take(rank).enumerate() {
+        u.set_column(c, &canonical_sign(&q * u_b.column(idx)));
+        s[c] = svd.singular_values[idx];
+    }
+    (u, s)
+}
+
+/// Indices of `values` sorted increasing, or decreasing if `descending`.
+fn sorted_indices(values: &DVector<f64>, descending: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+    if descending {
+        order.reverse();
+    }
+    order
+}
+
+/// Flips `v` so that its largest magnitude entry is positive.
+fn canonical_sign(v: DVector<f64>) -> DVector<f64> {
+    match v.iter().max_by(|a, b| a.abs().total_cmp(&b.abs())) {
+        Some(x) if *x < 0.0 => -v,
+        _ => v,
+    }
+}
+
+#[cfg(test)]
+mod spectral_tests {
+    use super::*;
+
+    #[test]
+    fn test_randomized_svd() {
+        // Rank 2 matrix with singular values 5 and 2.
+        let x = DVector::from_vec(vec![0.6, 0.8, 0.0, 0.0]);
+        let y = DVector::from_vec(vec![0.0, 0.0, 1.0, 0.0]);
+        let m = &x * x.transpose() * 5.0 + &y * y.transpose() * 2.0;
+        let (u, s) = randomized_svd(&m, 2);
+        assert!((s[0] - 5.0).abs() < 1e-9);
+        assert!((s[1] - 2.0).abs() < 1e-9);
+        assert!((u.column(0) - &x).norm() < 1e-9);
+        assert!((u.column(1) - &y).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_laplacian_eigenmaps() {
+        // Two triangles joined by one edge split by the Fiedler vector.
+        let mut g = Graph::new();
+        g.build(vec![
+            vec![0, 1],
+            vec![1, 2],
+            vec![2, 0],
+            vec![3, 4],
+            vec![4, 5],
+            vec![5, 3],
+            vec![2, 3],
+        ]);
+        let vectors = laplacian_eigenmaps(&g, 8);
+        assert_eq!(vectors.shape(), (8, 6));
+        let fiedler = |id| vectors[(0, *g.get_node_idx(&id).unwrap())];
+        assert!(fiedler(0) * fiedler(1) > 0.0);
+        assert!(fiedler(0) * fiedler(5) < 0.0);
+        // Only 5 non trivial eigenvectors exist.
+        assert_eq!(vectors.row(7).norm(), 0.0);
+    }
+
+    #[test]
+    fn test_netmf_is_deterministic() {
+        let mut g = Graph::new();
+        g.build(vec![
+            vec![0, 1],
+            vec![1, 2],
+            vec![2, 3],
+            vec![3, 0],
+            vec![0, 2],
+        ]);
+        let vectors = netmf(&g, 2, 1, 3);
+        assert_eq!(vectors.shape(), (3, 4));
+        assert_eq!(vectors, netmf(&g, 2, 1, 3));
+        assert!(vectors.iter().all(|x| x.is_finite()));
+    }
+}
@@ -0,0 +1,266 @@
+//! Summary statistics of a graph, to check an input before a long run.
+
+use crate::alias::AliasTable;
+use crate::graph::Graph;
+use crate::loader::LoadReport;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Wedges sampled for the clustering coefficient estimate.
+const WEDGE_SAMPLES: usize = 10_000;
+/// Component sizes listed, largest first.
+const LARGEST_COMPONENTS: usize = 10;
+const SEED: u64 = 42;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphStats {
+    pub nodes: usize,
+    /// Undirected edges, a self loop counts once.
+    pub edges: usize,
+    pub isolated_nodes: usize,
+    /// Self loops and repetitions of an edge beyond its first occurrence,
+    /// in the graph or, see `with_load_report`, in its input.
+    pub self_loops: usize,
+    pub duplicate_edges: usize,
+    pub weighted: bool,
+    /// Edges between distinct nodes over the number of node pairs.
+    pub density: f64,
+    pub degree: DegreeStats,
+    pub components: usize,
+    pub largest_components: Vec<usize>,
+    /// The fraction of sampled wedges, paths of length two, that are closed
+    /// by an edge, an estimate of the global clustering coefficient.
+    pub clustering_coefficient: f64,
+}
+
+impl GraphStats {
+    /// Takes the self loops and duplicate edges from the `report` of the
+    /// load, which counts them before the `EdgePolicy` dropped or merged
+    /// them.
+    pub fn with_load_report(self, report: &LoadReport) -> GraphStats {
+        GraphStats {
+            self_loops: report.self_loops,
+            duplicate_edges: report.duplicates,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DegreeStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Power of two buckets, `[0, 0]`, `[1, 1]`, `[2, 3]`, `[4, 7]`, ...
+    pub histogram: Vec<DegreeBucket>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DegreeBucket {
+    pub min: usize,
+    pub max: usize,
+    pub count: usize,
+}
+
+/// Computes the statistics of `graph`, degrees count neighbor list
+/// entries.  The clustering coefficient is sampled with a fixed seed.
+pub fn compute(graph: &Graph) -> GraphStats {
+    let node_ids = graph.node_ids_by_idx();
+    let n = node_ids.len();
+    let nodes: Vec<_> = node_ids
+        .iter()
+        .map(|id| graph.get_node(id).unwrap())
+        .collect();
+    let degrees: Vec<usize> = nodes.iter().map(|n| n.get_neighbors().len()).collect();
+
+    let (mut self_loops, mut duplicate_edges) = (0, 0);
+    let mut seen = HashMap::new();
+    for node in &nodes {
+        seen.clear();
+        for neighbor in node.get_neighbors() {
+            if *neighbor == node.get_id() {
+                self_loops += 1;
+            }
+            let count = seen.entry(*neighbor).or_insert(0);
+            *count += 1;
+            // Both ends list an edge, count it at the smaller id.
+            if *count > 1 && *neighbor >= node.get_id() {
+                duplicate_edges += 1;
+            }
+        }
+    }
+    let entries: usize = degrees.iter().sum();
+    let edges = (entries - self_loops) / 2 + self_loops;
+    let pairs = n as f64 * n.saturating_sub(1) as f64 / 2.0;
+    let density = if pairs > 0.0 {
+        (edges - self_loops) as f64 / pairs
+    } else {
+        0.0
+    };
+
+    let mut sizes = graph.component_sizes();
+    let components = sizes.len();
+    sizes.truncate(LARGEST_COMPONENTS);
+
+    GraphStats {
+        nodes: n,
+        edges,
+        isolated_nodes: degrees.iter().filter(|d| **d == 0).count(),
+        self_loops,
+        duplicate_edges,
+        weighted: graph.is_weighted(),
+        density,
+        degree: degree_stats(&degrees),
+        components,
+        largest_components: sizes,
+        clustering_coefficient: clustering_coefficient(graph),
+    }
+}
+
+fn degree_stats(degrees: &[usize]) -> DegreeStats {
+    let mut sorted = degrees.to_vec();
+    sorted.sort_unstable();
+    let n = sorted.len();
+    let median = match n {
+        0 => 0.0,
+        _ if n % 2 == 1 => sorted[n / 2] as f64,
+        _ => (sorted[n / 2 - 1] + sorted[n / 2]) as f64 / 2.0,
+    };
+
+    let bucket = |d: usize| match d {
+        0 => 0,
+        d => (usize::BITS - d.leading_zeros()) as usize,
+    };
+    let mut histogram = Vec::new();
+    for d in &sorted {
+        let b = bucket(*d);
+        while histogram.len() <= b {
+            let i = histogram.len();
+            let (min, max) = if i == 0 {
+                (0, 0)
+            } else {
+                (1 << (i - 1), (1 << i) - 1)
+            };
+            histogram.push(DegreeBucket { min, max, count: 0 });
+        }
+        histogram[b].count += 1;
+    }
+
+    DegreeStats {
+        min: sorted.first().copied().unwrap_or(0),
+        max: sorted.last().copied().unwrap_or(0),
+        mean: if n > 0 {
+            sorted.iter().sum::<usize>() as f64 / n as f64
+        } else {
+            0.0
+        },
+        median,
+        histogram,
+    }
+}
+
+/// Samples wedges `u - center - v` uniformly by choosing centers
+/// proportionally to their number of wedges and returns the closed
+/// fraction.  Self loops and repeated neighbors don't form wedges.
+fn clustering_coefficient(graph: &Graph) -> f64 {
+    let node_ids = graph.node_ids_by_idx();
+    let wedges: Vec<f64> = node_ids
+        .iter()
+        .map(|id| {
+            let d = graph.get_node(id).unwrap().get_neighbors().len() as f64;
+            d * (d - 1.0).max(0.0) / 2.0
+        })
+        .collect();
+    if wedges.iter().all(|w| *w == 0.0) {
+        return 0.0;
+    }
+    let centers = AliasTable::new(&wedges);
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let (mut sampled, mut closed) = (0, 0);
+    for _ in 0..WEDGE_SAMPLES {
+        let center = graph.get_node(&node_ids[centers.sample(&mut rng)]).unwrap();
+        let neighbors = center.get_neighbors();
+        let i = rng.gen_range(0, neighbors.len());
+        let j = rng.gen_range(0, neighbors.len() - 1);
+        let (u, v) = (neighbors[i], neighbors[if j >= i { j + 1 } else { j }]);
+        if u == v || u == center.get_id() || v == center.get_id() {
+            continue;
+        }
+        sampled += 1;
+        let (a, b) = (graph.get_node(&u).unwrap(), graph.get_node(&v).unwrap());
+        let (smaller, other) = if a.get_weight() <= b.get_weight() {
+            (a, v)
+        } else {
+            (b, u)
+        };
+        if smaller.get_neighbors().contains(&other) {
+            closed += 1;
+        }
+    }
+    if sampled == 0 {
+        0.0
+    } else {
+        closed as f64 / sampled as f64
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        // A triangle with a pendant node, a separate edge with a duplicate
+        // and a self loop, and an isolated node.
+        let mut g = Graph::new();
+        g.build(vec![
+            vec![1, 2],
+            vec![2, 3],
+            vec![3, 1],
+            vec![3, 4],
+            vec![5, 6],
+            vec![5, 6],
+            vec![6, 6],
+        ]);
+        g.add_node(7);
+        let stats = compute(&g);
+        assert_eq!(stats.nodes, 7);
+        assert_eq!(stats.edges, 7);
+        assert_eq!(stats.isolated_nodes, 1);
+        assert_eq!(stats.self_loops, 1);
+        assert_eq!(stats.duplicate_edges, 1);
+        assert!(!stats.weighted);
+        assert_eq!(stats.density, 6.0 / 21.0);
+        assert_eq!(stats.components, 3);
+        assert_eq!(stats.largest_components, vec![4, 2, 1]);
+
+        let degree = &stats.degree;
+        assert_eq!((degree.min, degree.max), (0, 3));
+        assert_eq!(degree.median, 2.0);
+        assert_eq!(degree.mean, 13.0 / 7.0);
+        let counts: Vec<usize> = degree.histogram.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![1, 1, 5]);
+        assert_eq!((degree.histogram[2].min, degree.histogram[2].max), (2, 3));
+    }
+
+    #[test]
+    fn test_clustering_coefficient() {
+        let mut g = Graph::new();
+        g.build(vec![vec![1, 2], vec![2, 3], vec![3, 1]]);
+        assert_eq!(clustering_coefficient(&g), 1.0);
+
+        let mut g = Graph::new();
+        g.build(vec![vec![1, 2], vec![1, 3], vec![1, 4]]);
+        assert_eq!(clustering_coefficient(&g), 0.0);
+
+        // Karate's transitivity is 0.2557.
+        let mut g = Graph::new();
+        g.build_graph_from_file("karate_network.txt").unwrap();
+        assert!((clustering_coefficient(&g) - 0.2557).abs() < 0.02);
+        let stats = g.stats();
+        assert_eq!((stats.nodes, stats.edges, stats.components), (34, 78, 1));
+    }
+}
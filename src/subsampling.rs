@@ -0,0 +1,86 @@
+use crate::graph::{Graph, NodeID};
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Word2vec style subsampling of frequent nodes.
+///
+/// Each occurrence of a node in a walk is dropped with probability
+/// `1 - sqrt(t / f)` where `t` is the `sample` threshold and `f` is the
+/// relative frequency of the node in the walk corpus.
+#[derive(Debug)]
+pub struct Subsampler {
+    keep_prob: Vec<f64>,
+}
+
+impl Subsampler {
+    pub fn new(graph: &Graph, frequencies: &HashMap<NodeID, u64>, sample: f64) -> Subsampler {
+        let total: u64 = frequencies.values().sum();
+        let mut keep_prob = vec![1.0; graph.num_nodes()];
+        if sample > 0.0 && total > 0 {
+            for (node_id, count) in frequencies {
+                if let Some(idx) = graph.get_node_idx(node_id) {
+                    let f = *count as f64 / total as f64;
+                    keep_prob[*idx] = (sample / f).sqrt().min(1.0);
+                }
+            }
+        }
+        Subsampler { keep_prob }
+    }
+
+    pub fn keep_prob(&self, node_idx: usize) -> f64 {
+        self.keep_prob[node_idx]
+    }
+
+    /// Removes occurrences from a walk of node indices, returning the number
+    /// dropped.
+    pub fn subsample<R: Rng>(&self, walk: &mut Vec<usize>, rng: &mut R) -> usize {
+        let before = walk.len();
+        walk.retain(|idx| {
+            let p = self.keep_prob[*idx];
+            p >= 1.0 || rng.gen::<f64>() < p
+        });
+        before - walk.len()
+    }
+}
+
+#[cfg(test)]
+mod subsampling_tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn star_graph() -> Graph {
+        let mut g = Graph::new();
+        g.build(vec![vec![0, 1], vec![0, 2], vec![0, 3], vec![0, 4]]);
+        g
+    }
+
+    #[test]
+    fn test_keep_prob() {
+        let g = star_graph();
+        let mut freqs = HashMap::new();
+        freqs.insert(0, 96);
+        freqs.insert(1, 1);
+        freqs.insert(2, 1);
+        freqs.insert(3, 1);
+        freqs.insert(4, 1);
+        let s = Subsampler::new(&g, &freqs, 0.01);
+        let hub = *g.get_node_idx(&0).unwrap();
+        let leaf = *g.get_node_idx(&1).unwrap();
+        assert!((s.keep_prob(hub) - (0.01f64 / 0.96).sqrt()).abs() < 1e-12);
+        assert_eq!(s.keep_prob(leaf), 1.0);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let g = star_graph();
+        let freqs = g.walk_frequencies(10);
+        let s = Subsampler::new(&g, &freqs, 0.0);
+        let mut walk: Vec<usize> = g
+            .random_walk(&0, 10)
+            .iter()
+            .map(|n| *g.get_node_idx(n).unwrap())
+            .collect();
+        assert_eq!(s.subsample(&mut walk, &mut thread_rng()), 0);
+        assert_eq!(walk.len(), 10);
+    }
+}